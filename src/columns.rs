@@ -0,0 +1,40 @@
+// Lists of names laid out in columns, for `words` and tab completion
+
+// Width of the terminal lines the columns are fitted into
+pub const LINE_WIDTH: usize = 80;
+
+// Rows of the names put in as many columns as fit in the width
+pub fn rows<S: AsRef<str>>(names: &[S], width: usize) -> Vec<String> {
+    let column = names
+        .iter()
+        .map(|n| n.as_ref().chars().count())
+        .max()
+        .unwrap_or(0)
+        + 2;
+    let columns = (width / column).max(1);
+
+    names
+        .chunks(columns)
+        .map(|row| {
+            let cells: Vec<_> = row
+                .iter()
+                .map(|n| format!("{:<1$}", n.as_ref(), column))
+                .collect();
+            cells.concat().trim_end().to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::rows;
+
+    #[test]
+    fn names_fill_the_width() {
+        let names = ["a", "bb", "ccc", "d", "e"];
+        assert_eq!(rows(&names, 10), ["a    bb", "ccc  d", "e"]);
+        assert_eq!(rows(&names, 80), ["a    bb   ccc  d    e"]);
+        assert_eq!(rows(&["long-name"], 4), ["long-name"]);
+        assert!(rows::<&str>(&[], 80).is_empty());
+    }
+}
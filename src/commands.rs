@@ -0,0 +1,453 @@
+use std::fs;
+use std::time::Instant;
+
+use editor::Helper;
+use forth::env::{ForthEnv, ForthResult, WordClass};
+use forth::help;
+use forth::inter::Interpreter;
+
+// REPL meta-commands, kept apart from the Forth dictionary. They start with
+// a colon directly followed by a letter, so `: name` is still a definition.
+const COMMANDS: &[(&str, &str)] = &[
+    (":stack", "show the stack and its depth"),
+    (":words", "list the words in the search order"),
+    (":dict", "list the user defined functions"),
+    (":vars", "show the variables and their values"),
+    (":reset", "start over with an empty stack and dictionary"),
+    (":load", "FILE  run the Forth source in the file"),
+    (":save", "FILE  write the source entered so far to the file"),
+    (":time", "EXPR  run the expression and show its run time"),
+    (":help", "[WORD]  describe the word, or list the commands"),
+    (":quit", "leave the REPL"),
+];
+
+// Words followed by the name of a word they define
+const DEFINING_WORDS: &[&str] = &[
+    ":",
+    "variable",
+    "constant",
+    "value",
+    "create",
+    "defer",
+    "vocabulary",
+    "marker",
+    "begin-structure",
+    "+field",
+    "field:",
+    "cfield:",
+    "method",
+    "var",
+    "end-class",
+];
+
+// Number of items from the top of the stack shown while typing
+const STACK_PREVIEW: usize = 5;
+
+pub fn is_command(line: &str) -> bool {
+    let mut chars = line.chars();
+    chars.next() == Some(':') && chars.next().is_some_and(char::is_alphabetic)
+}
+
+// Interpreter state along with the source evaluated so far
+pub struct Session {
+    pub env: ForthEnv,
+    intr: Interpreter,
+    source: Vec<String>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Session {
+            env: ForthEnv::empty(),
+            intr: Interpreter::new(),
+            source: vec![],
+        }
+    }
+
+    // Runs a line of Forth or a meta-command, returning false once the
+    // session should end
+    pub fn run(&mut self, line: &str) -> bool {
+        if !is_command(line) {
+            // Any error has been shown along with the stack
+            let _ = self.eval(line);
+            return true;
+        }
+
+        let (cmd, arg) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line, ""),
+        };
+        if cmd == ":quit" {
+            return false;
+        }
+        if let Err(e) = self.command(cmd, arg) {
+            println!("Error: {}", e);
+        }
+        true
+    }
+
+    fn eval(&mut self, line: &str) -> ForthResult<()> {
+        self.source.push(line.to_string());
+        self.intr.eval(&mut self.env, line)
+    }
+
+    fn command(&mut self, cmd: &str, arg: &str) -> ForthResult<()> {
+        match cmd {
+            ":stack" => self.env.print_depth_stack(),
+            ":words" => self.env.print_words(),
+            ":dict" => self.env.print_func(),
+            ":vars" => self.env.print_vars(),
+            ":reset" => {
+                self.env = ForthEnv::empty();
+                self.source.clear();
+            }
+            ":load" => {
+                let text = fs::read_to_string(required(cmd, arg)?)
+                    .map_err(|e| format!("Cannot read {}: {}", arg, e))?;
+                // Stops at the first error, as the rest of the file
+                // likely depends on what failed
+                for (i, line) in text.lines().enumerate() {
+                    self.eval(line.trim())
+                        .map_err(|_| format!("Stopped loading {} at line {}", arg, i + 1))?;
+                }
+            }
+            ":save" => {
+                let mut text = self.source.join("\n");
+                text.push('\n');
+                fs::write(required(cmd, arg)?, text)
+                    .map_err(|e| format!("Cannot write {}: {}", arg, e))?;
+                println!("Saved {} lines to {}", self.source.len(), arg);
+            }
+            ":time" => {
+                let start = Instant::now();
+                // Any error has been shown along with the stack
+                let _ = self.eval(required(cmd, arg)?);
+                println!("Time: {:?}", start.elapsed());
+            }
+            ":help" => self.help(arg)?,
+            _ => return Err(format!("Unknown command: {} (see :help)", cmd)),
+        }
+        Ok(())
+    }
+
+    fn help(&self, arg: &str) -> ForthResult<()> {
+        if arg.is_empty() {
+            for &(name, text) in COMMANDS {
+                println!("{:<8} {}", name, text);
+            }
+            return Ok(());
+        }
+
+        if let Some(&(name, text)) = COMMANDS.iter().find(|&&(name, _)| name == arg) {
+            println!("{} {}", name, text);
+            return Ok(());
+        }
+        let xt = match self.env.find(arg) {
+            Some(xt) => xt,
+            None => return Err(format!("Undefined word: {}", arg)),
+        };
+        match help::describe(arg) {
+            Some(text) if self.env.is_builtin(xt) => println!("{} {}", arg, text),
+            _ => println!("{}", self.env.decompile(xt)?),
+        }
+        Ok(())
+    }
+}
+
+fn required<'a>(cmd: &str, arg: &'a str) -> ForthResult<&'a str> {
+    if arg.is_empty() {
+        Err(format!("Missing argument for {}", cmd))
+    } else {
+        Ok(arg)
+    }
+}
+
+fn color(class: WordClass) -> &'static str {
+    match class {
+        WordClass::Builtin => "36",
+        WordClass::User => "32",
+        WordClass::Variable => "35",
+        WordClass::Constant => "33",
+        WordClass::Number => "34",
+        WordClass::Unknown => "31",
+    }
+}
+
+fn paint(text: &str, color: &str) -> String {
+    format!("\x1b[{}m{}\x1b[0m", color, text)
+}
+
+// Length of the text parsed by a word up to and including its terminator,
+// or all of the text if the terminator is missing
+fn parsed_len(text: &str, word: &str) -> usize {
+    let end = match word {
+        ".\"" | "s\"" | "c\"" => text.find('"'),
+        "(" => text.find(')'),
+        "\\" => None,
+        "s\\\"" => {
+            let mut escaped = false;
+            text.find(|c| {
+                let end = c == '"' && !escaped;
+                escaped = c == '\\' && !escaped;
+                end
+            })
+        }
+        _ => return 0,
+    };
+    end.map_or(text.len(), |i| i + 1)
+}
+
+impl Helper for Session {
+    fn complete(&self, text: &str) -> Vec<String> {
+        let mut words = text.split_whitespace().rev();
+        let (prefix, after) = if text.ends_with(char::is_whitespace) || text.is_empty() {
+            ("", words.next())
+        } else {
+            (words.next().unwrap_or(""), words.next())
+        };
+
+        if after.is_none() && prefix.starts_with(':') && prefix.len() > 1 {
+            return COMMANDS
+                .iter()
+                .map(|&(name, _)| name.to_string())
+                .filter(|name| name.starts_with(prefix))
+                .collect();
+        }
+        match after {
+            Some(":load") | Some(":save") => vec![],
+            Some(":help") | Some(":time") => self.env.completions(None, prefix),
+            _ => self.env.completions(after, prefix),
+        }
+    }
+
+    // Colors each word by what it is in the dictionary. Names being defined
+    // and the text read by parsing words are left as they are.
+    fn highlight(&self, text: &str) -> String {
+        if is_command(text) {
+            return text.to_string();
+        }
+
+        let mut out = String::new();
+        let mut rest = text;
+        let mut locals = vec![];
+        let mut declaring = None;
+        let mut defining = false;
+
+        loop {
+            let start = rest.len() - rest.trim_start().len();
+            out.push_str(&rest[..start]);
+            rest = &rest[start..];
+            if rest.is_empty() {
+                return out;
+            }
+
+            let len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let word = &rest[..len];
+            rest = &rest[len..];
+
+            if let Some(end) = declaring {
+                if word == end {
+                    declaring = None;
+                    out.push_str(&paint(word, color(WordClass::Builtin)));
+                } else {
+                    if word != "|" && word != "--" {
+                        locals.push(word);
+                    }
+                    out.push_str(word);
+                }
+                continue;
+            }
+            if defining {
+                defining = false;
+                out.push_str(word);
+                continue;
+            }
+
+            let class = if locals.contains(&word) {
+                WordClass::Variable
+            } else {
+                self.env.classify(word)
+            };
+            out.push_str(&paint(word, color(class)));
+
+            match word {
+                "{:" => declaring = Some(":}"),
+                "locals|" => declaring = Some("|"),
+                _ if DEFINING_WORDS.contains(&word) => defining = true,
+                _ => {
+                    let parsed = parsed_len(rest, word);
+                    out.push_str(&rest[..parsed]);
+                    rest = &rest[parsed..];
+                }
+            }
+        }
+    }
+
+    fn status(&self) -> Option<String> {
+        let summary = self.env.stack_summary(STACK_PREVIEW);
+        Some(paint(&format!("Stack {}", summary), "2"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{color, is_command, paint, parsed_len, Session};
+    use editor::Helper;
+    use forth::env::WordClass;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn completes_words_and_commands() {
+        let session = Session::new();
+        assert_eq!(session.complete("1 du"), ["dup"]);
+        assert_eq!(session.complete(":he"), [":help"]);
+        assert_eq!(session.complete(":s"), [":stack", ":save"]);
+        assert!(session.complete(":load fi").is_empty());
+        assert_eq!(session.complete(":help ov"), ["over"]);
+        assert!(session.complete("xyzzy").is_empty());
+    }
+
+    #[test]
+    fn completes_names_of_the_kind_expected() {
+        let mut session = Session::new();
+        session.run("variable v1 5 value v2 3 constant v3 : v4 ; defer v5");
+        assert_eq!(
+            session.complete("v"),
+            [
+                "v1",
+                "v2",
+                "v3",
+                "v4",
+                "v5",
+                "value",
+                "var",
+                "variable",
+                "vocabulary"
+            ]
+        );
+        assert_eq!(session.complete("to v"), ["v2"]);
+        assert_eq!(session.complete("1 +to "), ["v2"]);
+        assert_eq!(session.complete("is v"), ["v5"]);
+        assert_eq!(session.complete("variable v"), ["v1"]);
+        assert_eq!(session.complete(": v"), ["v4"]);
+    }
+
+    #[test]
+    fn commands_start_with_a_colon_and_a_letter() {
+        assert!(is_command(":help"));
+        assert!(is_command(":load file.fs"));
+        assert!(is_command(":x"));
+        assert!(!is_command(": sq dup * ;"));
+        assert!(!is_command(":"));
+        assert!(!is_command("::"));
+        assert!(!is_command("1 :help"));
+        assert!(!is_command(""));
+    }
+
+    #[test]
+    fn unfinished_input_continues_on_the_next_line() {
+        let mut session = Session::new();
+        for line in &[
+            ": sq",
+            "dup *",
+            "( a comment",
+            "continued )",
+            "s\" a",
+            "b\"",
+        ] {
+            session.run(line);
+            assert!(session.env.is_incomplete(), "{}", line);
+        }
+        session.run("drop drop ;");
+        assert!(!session.env.is_incomplete());
+
+        session.run("3 sq 1 if");
+        assert!(session.env.is_incomplete());
+        session.run("1 + then");
+        assert!(!session.env.is_incomplete());
+        assert_eq!(session.env.pop(String::new()), Ok(10));
+    }
+
+    #[test]
+    fn unfinished_input_can_be_dropped() {
+        let mut session = Session::new();
+        session.run(": f .\" abc");
+        assert!(session.env.is_incomplete());
+        session.env.cancel_input();
+        assert!(!session.env.is_incomplete());
+        assert!(session.env.find("f").is_none());
+
+        session.run(": g");
+        session.run("nope ;");
+        assert!(!session.env.is_incomplete());
+        assert!(session.env.find("g").is_none());
+    }
+
+    #[test]
+    fn parsing_words_take_text_up_to_their_terminator() {
+        assert_eq!(parsed_len(" abc\" 1", ".\""), 5);
+        assert_eq!(parsed_len(" abc", "s\""), 4);
+        assert_eq!(parsed_len(" a) b", "("), 3);
+        assert_eq!(parsed_len(" rest of line", "\\"), 13);
+        assert_eq!(parsed_len(" a\\\" b\" c", "s\\\""), 7);
+        assert_eq!(parsed_len(" a\\\\\" b", "s\\\""), 5);
+        assert_eq!(parsed_len(" dup", "dup"), 0);
+    }
+
+    #[test]
+    fn highlights_words_by_kind() {
+        let mut session = Session::new();
+        session.run("variable v 3 constant k : f ;");
+        let word = |text, class| paint(text, color(class));
+
+        let expected = [
+            word("1", WordClass::Number),
+            word("dup", WordClass::Builtin),
+            word("v", WordClass::Variable),
+            word("k", WordClass::Constant),
+            word("f", WordClass::User),
+            word("nope", WordClass::Unknown),
+        ]
+        .join(" ");
+        assert_eq!(session.highlight("1 dup v k f nope"), expected);
+        assert_eq!(session.highlight(":help dup"), ":help dup");
+    }
+
+    #[test]
+    fn highlighting_leaves_names_and_parsed_text_alone() {
+        let session = Session::new();
+        let builtin = |text| paint(text, color(WordClass::Builtin));
+
+        let text = format!("{} dup {} {}", builtin(":"), builtin("dup"), builtin(";"));
+        assert_eq!(session.highlight(": dup dup ;"), text);
+        let text = format!("{} dup 1\" {}", builtin(".\""), builtin("dup"));
+        assert_eq!(session.highlight(".\" dup 1\" dup"), text);
+
+        let local = paint("a", color(WordClass::Variable));
+        let text = format!("{} a {} {}", builtin("{:"), builtin(":}"), local);
+        assert_eq!(session.highlight("{: a :} a"), text);
+    }
+
+    #[test]
+    fn status_shows_the_top_of_the_stack() {
+        let mut session = Session::new();
+        session.run("1 2 3 4 5 6");
+        assert_eq!(session.status(), Some(paint("Stack <6> .. 2 3 4 5 6", "2")));
+    }
+
+    #[test]
+    fn load_stops_at_the_first_error() {
+        let path = env::temp_dir().join(format!("forth-load-{}.fs", process::id()));
+        fs::write(&path, ": f\nnope\n1 2 + ;\n").unwrap();
+        let path = path.to_str().unwrap().to_string();
+
+        let mut session = Session::new();
+        let res = session.command(":load", &path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(res, Err(format!("Stopped loading {} at line 2", path)));
+        assert_eq!(session.env.depth(), 0);
+        assert!(!session.env.is_incomplete());
+    }
+}
@@ -0,0 +1,542 @@
+// Line editor for the REPL, working on the terminal in raw mode
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use columns::{self, LINE_WIDTH};
+
+const HISTORY_FILE: &str = ".forth_history";
+const HISTORY_SIZE: usize = 1000;
+
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        Err(io::Error::other("Cannot set terminal mode"))
+    }
+}
+
+// Keeps the terminal in raw mode for as long as it is alive
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enable() -> io::Result<RawMode> {
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+        Ok(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+enum Key {
+    Char(char),
+    // Control key together with the lower case letter
+    Ctrl(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    Escape,
+    Unknown,
+}
+
+fn read_byte(input: &mut dyn Read) -> io::Result<Option<u8>> {
+    let mut buf = [0; 1];
+    match input.read(&mut buf)? {
+        0 => Ok(None),
+        _ => Ok(Some(buf[0])),
+    }
+}
+
+// Reads the rest of an escape sequence after the escape byte
+fn read_escape(input: &mut dyn Read) -> io::Result<Key> {
+    let kind = match read_byte(input)? {
+        Some(b) if b == b'[' || b == b'O' => b,
+        _ => return Ok(Key::Escape),
+    };
+
+    let mut params = vec![];
+    loop {
+        match read_byte(input)? {
+            Some(b) if (0x40..0x7f).contains(&b) => {
+                return Ok(match (kind, b, params.as_slice()) {
+                    (_, b'A', _) => Key::Up,
+                    (_, b'B', _) => Key::Down,
+                    (_, b'C', _) => Key::Right,
+                    (_, b'D', _) => Key::Left,
+                    (_, b'H', _) => Key::Home,
+                    (_, b'F', _) => Key::End,
+                    (b'[', b'~', b"1") | (b'[', b'~', b"7") => Key::Home,
+                    (b'[', b'~', b"4") | (b'[', b'~', b"8") => Key::End,
+                    (b'[', b'~', b"3") => Key::Delete,
+                    _ => Key::Unknown,
+                });
+            }
+            Some(b) => params.push(b),
+            None => return Ok(Key::Unknown),
+        }
+    }
+}
+
+fn read_key(input: &mut dyn Read) -> io::Result<Option<Key>> {
+    let first = match read_byte(input)? {
+        Some(b) => b,
+        None => return Ok(None),
+    };
+
+    let key = match first {
+        b'\r' | b'\n' => Key::Enter,
+        8 | 127 => Key::Backspace,
+        27 => read_escape(input)?,
+        1..=26 => Key::Ctrl((b'a' + first - 1) as char),
+        0..=31 => Key::Unknown,
+        _ => {
+            // Collect the continuation bytes of a UTF-8 character
+            let len = match first {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf7 => 4,
+                _ => 1,
+            };
+            let mut bytes = vec![first];
+            for _ in 1..len {
+                match read_byte(input)? {
+                    Some(b) => bytes.push(b),
+                    None => break,
+                }
+            }
+            match String::from_utf8(bytes) {
+                Ok(s) => s.chars().next().map_or(Key::Unknown, Key::Char),
+                Err(_) => Key::Unknown,
+            }
+        }
+    };
+    Ok(Some(key))
+}
+
+// Knowledge of the language being edited
+pub trait Helper {
+    // Candidates for the last word of the text, which is the line up to the
+    // cursor
+    fn complete(&self, text: &str) -> Vec<String>;
+
+    // Text with colors added, taking up the same columns on the terminal
+    fn highlight(&self, text: &str) -> String {
+        text.to_string()
+    }
+
+    // Line shown below the one being edited
+    fn status(&self) -> Option<String> {
+        None
+    }
+}
+
+// Moves to the next line, clearing any status line there
+fn new_line() -> io::Result<()> {
+    print!("\r\n\x1b[K");
+    io::stdout().flush()
+}
+
+fn common_prefix(words: &[String]) -> String {
+    let mut prefix: Vec<char> = words[0].chars().collect();
+    for word in &words[1..] {
+        let len = prefix
+            .iter()
+            .zip(word.chars())
+            .take_while(|&(&a, b)| a == b)
+            .count();
+        prefix.truncate(len);
+    }
+    prefix.into_iter().collect()
+}
+
+// Prints the words in columns below the line being edited
+fn print_candidates(words: &[String]) {
+    print!("\r\n\x1b[K");
+    for row in columns::rows(words, LINE_WIDTH) {
+        print!("{}\r\n", row);
+    }
+}
+
+// Text being edited, with the cursor position in characters
+struct Line {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Line {
+    fn new() -> Line {
+        Line {
+            chars: vec![],
+            pos: 0,
+        }
+    }
+
+    fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    fn set(&mut self, text: &str) {
+        self.chars = text.chars().collect();
+        self.pos = self.chars.len();
+    }
+
+    fn insert(&mut self, c: char) {
+        self.chars.insert(self.pos, c);
+        self.pos += 1;
+    }
+
+    fn backspace(&mut self) {
+        if self.pos > 0 {
+            self.pos -= 1;
+            self.chars.remove(self.pos);
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.pos < self.chars.len() {
+            self.chars.remove(self.pos);
+        }
+    }
+
+    // Start of the word the cursor is at the end of
+    fn word_start(&self) -> usize {
+        let mut start = self.pos;
+        while start > 0 && !self.chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        start
+    }
+
+    // Replaces the word before the cursor
+    fn replace_word(&mut self, word: &str) {
+        let start = self.word_start();
+        self.chars.splice(start..self.pos, word.chars());
+        self.pos = start + word.chars().count();
+    }
+
+    // Deletes the word before the cursor, along with any spaces after it
+    fn delete_word(&mut self) {
+        let mut start = self.pos;
+        while start > 0 && self.chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        while start > 0 && !self.chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        self.chars.drain(start..self.pos);
+        self.pos = start;
+    }
+}
+
+pub struct Editor {
+    history: Vec<String>,
+    history_file: Option<PathBuf>,
+}
+
+impl Editor {
+    // Editor with the history saved in the previous sessions
+    pub fn new() -> Editor {
+        let history_file = env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+        let mut editor = Editor {
+            history: vec![],
+            history_file,
+        };
+        editor.load_history();
+        editor
+    }
+
+    fn load_history(&mut self) {
+        let path = match self.history_file {
+            Some(ref path) => path,
+            None => return,
+        };
+
+        if let Ok(text) = fs::read_to_string(path) {
+            self.history = text.lines().map(|l| l.to_string()).collect();
+            if self.history.len() > HISTORY_SIZE {
+                let extra = self.history.len() - HISTORY_SIZE;
+                self.history.drain(..extra);
+                let _ = fs::write(path, self.history.join("\n") + "\n");
+            }
+        }
+    }
+
+    // Adds the line to the history, saving it for later sessions as well
+    pub fn add_history(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().map(|l| l.as_str()) == Some(line) {
+            return;
+        }
+        self.history.push(line.to_string());
+
+        if let Some(ref path) = self.history_file {
+            let file = OpenOptions::new().create(true).append(true).open(path);
+            if let Ok(mut file) = file {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+    }
+
+    // Reads a line from the terminal, or None at the end of input. Ctrl-C
+    // gives an `Interrupted` error.
+    pub fn read_line(&mut self, prompt: &str, helper: &dyn Helper) -> io::Result<Option<String>> {
+        let _raw = RawMode::enable()?;
+        let stdin = io::stdin();
+        let mut input = stdin.lock();
+
+        let mut line = Line::new();
+        let mut entry = self.history.len();
+        let mut draft = String::new();
+        let mut listed = false;
+
+        self.refresh(prompt, &line, helper)?;
+        loop {
+            let key = match read_key(&mut input)? {
+                Some(key) => key,
+                None => return Ok(None),
+            };
+
+            let tab = matches!(key, Key::Ctrl('i'));
+            match key {
+                Key::Ctrl('i') => {
+                    self.complete(&mut line, helper, listed);
+                    listed = true;
+                }
+                Key::Enter => {
+                    new_line()?;
+                    return Ok(Some(line.text()));
+                }
+                Key::Ctrl('d') if line.chars.is_empty() => {
+                    new_line()?;
+                    return Ok(None);
+                }
+                Key::Ctrl('c') => {
+                    print!("^C");
+                    new_line()?;
+                    return Err(io::Error::from(io::ErrorKind::Interrupted));
+                }
+                Key::Char(c) => line.insert(c),
+                Key::Backspace => line.backspace(),
+                Key::Delete | Key::Ctrl('d') => line.delete(),
+                Key::Left | Key::Ctrl('b') => line.pos = line.pos.saturating_sub(1),
+                Key::Right | Key::Ctrl('f') => line.pos = (line.pos + 1).min(line.chars.len()),
+                Key::Home | Key::Ctrl('a') => line.pos = 0,
+                Key::End | Key::Ctrl('e') => line.pos = line.chars.len(),
+                Key::Ctrl('k') => line.chars.truncate(line.pos),
+                Key::Ctrl('u') => {
+                    line.chars.drain(..line.pos);
+                    line.pos = 0;
+                }
+                Key::Ctrl('w') => line.delete_word(),
+                Key::Ctrl('l') => print!("\x1b[H\x1b[2J"),
+                Key::Up | Key::Ctrl('p') if entry > 0 => {
+                    if entry == self.history.len() {
+                        draft = line.text();
+                    }
+                    entry -= 1;
+                    line.set(&self.history[entry]);
+                }
+                Key::Down | Key::Ctrl('n') if entry < self.history.len() => {
+                    entry += 1;
+                    match self.history.get(entry) {
+                        Some(text) => line.set(text),
+                        None => line.set(&draft),
+                    }
+                }
+                Key::Ctrl('r') if self.search(&mut input, &mut line)? => {
+                    new_line()?;
+                    return Ok(Some(line.text()));
+                }
+                _ => {}
+            }
+            if !tab {
+                listed = false;
+            }
+            self.refresh(prompt, &line, helper)?;
+        }
+    }
+
+    // Completes the word before the cursor as far as it is unambiguous, and
+    // lists the candidates when tab is pressed again
+    fn complete(&self, line: &mut Line, helper: &dyn Helper, list: bool) {
+        let text: String = line.chars[..line.pos].iter().collect();
+        let prefix_len = line.pos - line.word_start();
+        let candidates = helper.complete(&text);
+
+        match candidates.len() {
+            0 => print!("\x07"),
+            1 => line.replace_word(&format!("{} ", candidates[0])),
+            _ => {
+                let common = common_prefix(&candidates);
+                if common.chars().count() > prefix_len {
+                    line.replace_word(&common);
+                } else if list {
+                    print_candidates(&candidates);
+                } else {
+                    print!("\x07");
+                }
+            }
+        }
+    }
+
+    // Redraws the line along with the status line below it, and puts the
+    // cursor back in place
+    fn refresh(&self, prompt: &str, line: &Line, helper: &dyn Helper) -> io::Result<()> {
+        let mut out = io::stdout();
+        write!(out, "\r{}{}\x1b[K", prompt, helper.highlight(&line.text()))?;
+        if let Some(status) = helper.status() {
+            write!(out, "\r\n{}\x1b[K\x1b[A", status)?;
+        }
+        write!(out, "\r")?;
+        let col = prompt.chars().count() + line.pos;
+        if col > 0 {
+            write!(out, "\x1b[{}C", col)?;
+        }
+        out.flush()
+    }
+
+    // Finds the newest history entry before the given one containing the
+    // query
+    fn find_history(&self, query: &str, before: usize) -> Option<usize> {
+        self.history[..before]
+            .iter()
+            .rposition(|l| l.contains(query))
+    }
+
+    // Reverse incremental search through the history. The match found is
+    // put in the line, and true is returned if it should be run right away.
+    fn search(&self, input: &mut dyn Read, line: &mut Line) -> io::Result<bool> {
+        let mut query = String::new();
+        let mut found: Option<usize> = None;
+
+        loop {
+            let text = found.map_or("", |i| self.history[i].as_str());
+            print!("\r(reverse-i-search)`{}': {}\x1b[K", query, text);
+            io::stdout().flush()?;
+
+            match read_key(input)? {
+                Some(Key::Char(c)) => {
+                    query.push(c);
+                    let before = found.map_or(self.history.len(), |i| i + 1);
+                    found = self.find_history(&query, before);
+                }
+                Some(Key::Backspace) => {
+                    query.pop();
+                    found = self.find_history(&query, self.history.len());
+                }
+                Some(Key::Ctrl('r')) => {
+                    let before = found.unwrap_or(self.history.len());
+                    if let Some(i) = self.find_history(&query, before) {
+                        found = Some(i);
+                    }
+                }
+                Some(Key::Enter) => {
+                    if let Some(i) = found {
+                        line.set(&self.history[i]);
+                    }
+                    return Ok(true);
+                }
+                Some(Key::Ctrl('g')) | Some(Key::Escape) | None => return Ok(false),
+                Some(_) => {
+                    if let Some(i) = found {
+                        line.set(&self.history[i]);
+                    }
+                    return Ok(false);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{common_prefix, Line};
+
+    fn line(text: &str, pos: usize) -> Line {
+        Line {
+            chars: text.chars().collect(),
+            pos,
+        }
+    }
+
+    #[test]
+    fn edits_at_the_cursor() {
+        let mut l = Line::new();
+        for c in "1 2+".chars() {
+            l.insert(c);
+        }
+        l.pos = 3;
+        l.insert(' ');
+        assert_eq!((l.text().as_str(), l.pos), ("1 2 +", 4));
+
+        l.backspace();
+        assert_eq!((l.text().as_str(), l.pos), ("1 2+", 3));
+        l.delete();
+        assert_eq!((l.text().as_str(), l.pos), ("1 2", 3));
+        l.delete();
+        assert_eq!(l.text(), "1 2");
+
+        l.pos = 0;
+        l.backspace();
+        assert_eq!((l.text().as_str(), l.pos), ("1 2", 0));
+        l.set("é");
+        l.backspace();
+        assert_eq!((l.text().as_str(), l.pos), ("", 0));
+    }
+
+    #[test]
+    fn deletes_the_word_before_the_cursor() {
+        let mut l = line("1 dup  ", 7);
+        l.delete_word();
+        assert_eq!((l.text().as_str(), l.pos), ("1 ", 2));
+
+        let mut l = line("1 dup drop", 5);
+        l.delete_word();
+        assert_eq!((l.text().as_str(), l.pos), ("1  drop", 2));
+
+        let mut l = line("  ", 2);
+        l.delete_word();
+        assert_eq!((l.text().as_str(), l.pos), ("", 0));
+    }
+
+    #[test]
+    fn completions_share_a_prefix() {
+        let words = ["dup".to_string(), "drop".to_string()];
+        assert_eq!(common_prefix(&words), "d");
+        let words = ["évalue".to_string(), "évaluer".to_string()];
+        assert_eq!(common_prefix(&words), "évalue");
+    }
+
+    #[test]
+    fn completion_replaces_the_word_before_the_cursor() {
+        let mut l = line("1 du 2", 4);
+        assert_eq!(l.word_start(), 2);
+        l.replace_word("dup ");
+        assert_eq!(l.text(), "1 dup  2");
+        assert_eq!(l.pos, 6);
+
+        let mut l = line("ü", 1);
+        assert_eq!(l.word_start(), 0);
+        l.replace_word("über");
+        assert_eq!((l.text().as_str(), l.pos), ("über", 4));
+    }
+}
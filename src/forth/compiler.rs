@@ -0,0 +1,305 @@
+use forth::env::{ForthResult, Op};
+
+// Threaded code instruction. Branch targets are indices into the code of the
+// function being run.
+#[derive(Clone, Copy)]
+pub(crate) enum Instr {
+    // Runs the op of the builtin word with the execution token
+    Builtin(Op, usize),
+    Literal(i32),
+    // Pushes an execution token
+    Xt(usize),
+    // Pushes the address and length of a string in data space
+    String(i32, i32),
+    // Pushes the address of a counted string in data space
+    CString(i32),
+    // Prints a string in data space, compiled by `."`
+    Print(i32, i32),
+    Call(usize),
+    Branch(usize),
+    BranchIfZero(usize),
+    // Starts a loop, or jumps past it when there is nothing to iterate
+    Do(usize),
+    // Jumps back to the loop body until the loop index reaches its limit
+    Loop(usize),
+    // Adds the word to whichever definition is being compiled
+    Compile(usize),
+    // Starts a frame of locals, the first taken from the stack and the rest
+    // set to zero
+    Locals(usize, usize),
+    // Pushes a local from the current frame
+    Local(usize),
+    // Stores into a local of the current frame
+    ToLocal(usize),
+    // Adds to a local of the current frame
+    PlusToLocal(usize),
+    // Stores into or adds to the value with the execution token
+    ToValue(usize),
+    PlusToValue(usize),
+    // Sets or pushes the action of the deferred word with the execution token
+    Is(usize),
+    ActionOf(usize),
+    // Drops the current frame of locals
+    EndLocals,
+    // Makes the code of the function from the given position on the action
+    // of the most recently created word, and returns
+    Does(usize, usize),
+}
+
+enum Control {
+    If(usize),
+    Else(usize),
+    Do(usize),
+}
+
+// Builds the code of a definition, keeping track of the control structures
+// which are still open so that their branches can be patched
+pub(crate) struct Compiler {
+    code: Vec<Instr>,
+    control: Vec<Control>,
+    func: Option<(String, usize)>,
+    stack_effect: Option<String>,
+    locals: Vec<String>,
+    // Declaration of the locals as it was written
+    locals_decl: Option<String>,
+}
+
+impl Compiler {
+    // Compiler for code which is not part of a definition
+    pub fn new() -> Self {
+        Compiler {
+            code: vec![],
+            control: vec![],
+            func: None,
+            stack_effect: None,
+            locals: vec![],
+            locals_decl: None,
+        }
+    }
+
+    // Compiler for the body of the named function which will get the given
+    // execution token
+    pub fn for_function(name: &str, func: usize) -> Self {
+        Compiler {
+            code: vec![],
+            control: vec![],
+            func: Some((name.to_string(), func)),
+            stack_effect: None,
+            locals: vec![],
+            locals_decl: None,
+        }
+    }
+
+    // Name and execution token of the function being defined
+    pub fn function(&self) -> Option<(&str, usize)> {
+        self.func.as_ref().map(|f| (f.0.as_str(), f.1))
+    }
+
+    // Keeps a comment as the stack effect of the function, if it comes
+    // before any of its code
+    pub fn comment(&mut self, text: &str) {
+        if self.func.is_some() && self.code.is_empty() && self.stack_effect.is_none() {
+            self.stack_effect = Some(text.trim().to_string());
+        }
+    }
+
+    pub fn stack_effect(&self) -> Option<String> {
+        self.stack_effect.clone()
+    }
+
+    // Declares the locals of the function, the first few of which get
+    // their values from the stack. The frame lasts until the end of the
+    // function, so it cannot be started inside a control structure.
+    pub fn declare_locals(
+        &mut self,
+        names: Vec<String>,
+        from_stack: usize,
+        decl: String,
+    ) -> ForthResult<()> {
+        if self.func.is_none() {
+            return Err("Locals used outside of a definition".to_string());
+        }
+        if self.has_locals() {
+            return Err("Locals already declared".to_string());
+        }
+        if self.is_nested() {
+            return Err("Locals declared inside a control structure".to_string());
+        }
+
+        self.emit(Instr::Locals(from_stack, names.len() - from_stack));
+        self.locals = names;
+        self.locals_decl = Some(decl);
+        Ok(())
+    }
+
+    // Whether the function has declared its locals, even if there are none
+    fn has_locals(&self) -> bool {
+        self.locals_decl.is_some()
+    }
+
+    // Frame index of the named local
+    pub fn local(&self, name: &str) -> Option<usize> {
+        self.locals.iter().position(|l| l == name)
+    }
+
+    pub fn locals(&self) -> Vec<String> {
+        self.locals.clone()
+    }
+
+    pub fn locals_decl(&self) -> Option<String> {
+        self.locals_decl.clone()
+    }
+
+    pub fn emit(&mut self, instr: Instr) {
+        self.code.push(instr);
+    }
+
+    pub fn recurse(&mut self) -> ForthResult<()> {
+        match self.func {
+            Some((_, func)) => {
+                self.emit(Instr::Call(func));
+                Ok(())
+            }
+            None => Err("recurse used outside of a definition".to_string()),
+        }
+    }
+
+    // Ends the part of the function which runs when defining a word, the
+    // rest becomes the action of the defined word
+    pub fn does(&mut self) -> ForthResult<()> {
+        let func = match self.func {
+            Some((_, func)) => func,
+            None => return Err("does> used outside of a definition".to_string()),
+        };
+        if self.has_locals() {
+            return Err("does> cannot be used with locals".to_string());
+        }
+        if self.is_nested() {
+            return Err("does> used inside a control structure".to_string());
+        }
+
+        let pos = self.code.len() + 1;
+        self.emit(Instr::Does(func, pos));
+        Ok(())
+    }
+
+    pub fn is_nested(&self) -> bool {
+        !self.control.is_empty()
+    }
+
+    fn patch(&mut self, pos: usize) {
+        let target = self.code.len();
+        self.code[pos] = match self.code[pos] {
+            Instr::Branch(_) => Instr::Branch(target),
+            Instr::BranchIfZero(_) => Instr::BranchIfZero(target),
+            Instr::Do(_) => Instr::Do(target),
+            instr => instr,
+        };
+    }
+
+    pub fn begin_if(&mut self) {
+        self.control.push(Control::If(self.code.len()));
+        self.emit(Instr::BranchIfZero(0));
+    }
+
+    pub fn begin_else(&mut self) -> ForthResult<()> {
+        match self.control.pop() {
+            Some(Control::If(pos)) => {
+                self.control.push(Control::Else(self.code.len()));
+                self.emit(Instr::Branch(0));
+                self.patch(pos);
+                Ok(())
+            }
+            _ => Err("else without matching if".to_string()),
+        }
+    }
+
+    pub fn end_if(&mut self) -> ForthResult<()> {
+        match self.control.pop() {
+            Some(Control::If(pos)) | Some(Control::Else(pos)) => {
+                self.patch(pos);
+                Ok(())
+            }
+            _ => Err("then without matching if".to_string()),
+        }
+    }
+
+    pub fn begin_do(&mut self) {
+        self.control.push(Control::Do(self.code.len()));
+        self.emit(Instr::Do(0));
+    }
+
+    pub fn end_do(&mut self) -> ForthResult<()> {
+        match self.control.pop() {
+            Some(Control::Do(pos)) => {
+                self.emit(Instr::Loop(pos + 1));
+                self.patch(pos);
+                Ok(())
+            }
+            _ => Err("loop without matching do".to_string()),
+        }
+    }
+
+    pub fn finish(mut self) -> ForthResult<Vec<Instr>> {
+        if self.has_locals() {
+            self.emit(Instr::EndLocals);
+        }
+
+        match self.control.last() {
+            None => Ok(self.code),
+            Some(Control::If(_)) | Some(Control::Else(_)) => Err("Unterminated if".to_string()),
+            Some(Control::Do(_)) => Err("Unterminated do loop".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Compiler, Instr};
+
+    #[test]
+    fn patches_if_else_branches() {
+        let mut comp = Compiler::new();
+        comp.begin_if();
+        comp.emit(Instr::Literal(1));
+        comp.begin_else().unwrap();
+        comp.emit(Instr::Literal(2));
+        comp.end_if().unwrap();
+        let code = comp.finish().unwrap();
+
+        assert!(matches!(code[0], Instr::BranchIfZero(3)));
+        assert!(matches!(code[2], Instr::Branch(4)));
+        assert_eq!(code.len(), 4);
+    }
+
+    #[test]
+    fn patches_do_loops() {
+        let mut comp = Compiler::new();
+        comp.emit(Instr::Literal(0));
+        comp.begin_do();
+        comp.emit(Instr::Literal(1));
+        comp.end_do().unwrap();
+        let code = comp.finish().unwrap();
+
+        assert!(matches!(code[1], Instr::Do(4)));
+        assert!(matches!(code[3], Instr::Loop(2)));
+    }
+
+    #[test]
+    fn rejects_unbalanced_control() {
+        assert!(Compiler::new().end_if().is_err());
+        assert!(Compiler::new().begin_else().is_err());
+        assert!(Compiler::new().end_do().is_err());
+
+        let mut comp = Compiler::new();
+        comp.begin_if();
+        assert!(comp.end_do().is_err());
+
+        let mut comp = Compiler::new();
+        comp.begin_do();
+        assert_eq!(
+            comp.finish().err(),
+            Some("Unterminated do loop".to_string())
+        );
+    }
+}
@@ -0,0 +1,315 @@
+use forth::compiler::Instr;
+use forth::env::{Action, ForthEnv, ForthResult, Word, WordKind};
+use forth::number;
+
+// Control structure still open while decompiling, with the position its
+// branch jumps to
+enum Open {
+    If(usize),
+    Else(usize),
+    Do,
+}
+
+// Source text being built up line by line, with control structures
+// indenting their bodies
+struct Source {
+    lines: Vec<(usize, String)>,
+    line: Vec<String>,
+    indent: usize,
+}
+
+impl Source {
+    fn word(&mut self, word: String) {
+        self.line.push(word);
+    }
+
+    fn flush(&mut self) {
+        if !self.line.is_empty() {
+            self.lines.push((self.indent, self.line.join(" ")));
+            self.line.clear();
+        }
+    }
+
+    // Ends the line with the word and indents what follows
+    fn open(&mut self, word: &str) {
+        self.word(word.to_string());
+        self.flush();
+        self.indent += 1;
+    }
+
+    // Puts the word on a line of its own between two indented bodies
+    fn middle(&mut self, word: &str) {
+        self.flush();
+        self.lines.push((self.indent - 1, word.to_string()));
+    }
+
+    // Starts a line with the word, going back to the outer indentation
+    fn close(&mut self, word: &str) {
+        self.flush();
+        self.indent -= 1;
+        self.word(word.to_string());
+    }
+}
+
+// Translates bytes back into the body of a `s\"` string
+fn escape(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for c in String::from_utf8_lossy(bytes).chars() {
+        match c {
+            '\n' => text.push_str("\\n"),
+            '\t' => text.push_str("\\t"),
+            '\r' => text.push_str("\\r"),
+            '"' => text.push_str("\\q"),
+            '\\' => text.push_str("\\\\"),
+            c if c.is_control() => text.push_str(&format!("\\x{:02X}", c as u32)),
+            c => text.push(c),
+        }
+    }
+    text
+}
+
+// Name of the local at the index in the frame of the function
+fn local_name(word: &Word, index: usize) -> String {
+    match word.locals.get(index) {
+        Some(name) => name.clone(),
+        None => "<local>".to_string(),
+    }
+}
+
+fn needs_escape(bytes: &[u8]) -> bool {
+    bytes.iter().any(|&b| b == b'"' || b < 0x20 || b == 0x7F)
+}
+
+// Turns words back into source text for `see`
+impl ForthEnv {
+    pub fn decompile(&self, xt: usize) -> ForthResult<String> {
+        let word = self.word(xt)?;
+        let name = &word.name;
+        let base = self.base()?;
+
+        let text = match word.kind {
+            WordKind::Builtin(_) => format!("\\ {} is a builtin word", name),
+            WordKind::Function(ref code) => self.decompile_function(xt, code)?,
+            WordKind::Constant(x) => {
+                format!("{} constant {}", number::format_number(x, base), name)
+            }
+            WordKind::Variable(addr) => format!(
+                "variable {}\n{} {} !",
+                name,
+                number::format_number(self.fetch(addr)?, base),
+                name
+            ),
+            WordKind::Deferred(Some(action)) => {
+                format!("defer {}\n' {} is {}", name, self.word(action)?.name, name)
+            }
+            WordKind::Value(addr) => {
+                format!(
+                    "{} value {}",
+                    number::format_number(self.fetch(addr)?, base),
+                    name
+                )
+            }
+            WordKind::Field(offset) => format!("\\ {} is a field at offset {}", name, offset),
+            WordKind::Created(_, None) => format!("create {}", name),
+            WordKind::Created(_, Some(Action::Code(func, _))) => {
+                format!("create {} \\ does> of {}", name, self.word(func)?.name)
+            }
+            WordKind::Created(addr, Some(Action::Dispatch)) => {
+                format!("\\ {} is a method at offset {}", name, self.fetch(addr)?)
+            }
+            WordKind::Created(addr, Some(Action::VarField)) => format!(
+                "\\ {} is an instance variable at offset {}",
+                name,
+                self.fetch(addr)?
+            ),
+            WordKind::Deferred(None) => format!("defer {}", name),
+            WordKind::Vocabulary(_) => format!("vocabulary {}", name),
+            WordKind::Marker(_) => format!("marker {}", name),
+        };
+
+        if word.immediate {
+            Ok(text + " immediate")
+        } else {
+            Ok(text)
+        }
+    }
+
+    fn decompile_function(&self, xt: usize, code: &[Instr]) -> ForthResult<String> {
+        let word = self.word(xt)?;
+        let base = self.base()?;
+        let mut src = Source {
+            lines: vec![],
+            line: vec![],
+            indent: 0,
+        };
+        let mut control = vec![];
+
+        let mut ip = 0;
+        while ip <= code.len() {
+            while let Some(&Open::If(end)) | Some(&Open::Else(end)) = control.last() {
+                if end != ip {
+                    break;
+                }
+                control.pop();
+                src.close("then");
+            }
+            if ip == code.len() {
+                break;
+            }
+
+            match code[ip] {
+                Instr::Builtin(_, x) => src.word(self.word(x)?.name.clone()),
+                Instr::Literal(n) => src.word(number::format_number(n, base)),
+                Instr::Xt(x) => src.word(format!("['] {}", self.word(x)?.name)),
+                Instr::ToValue(x) => src.word(format!("to {}", self.word(x)?.name)),
+                Instr::PlusToValue(x) => src.word(format!("+to {}", self.word(x)?.name)),
+                Instr::Is(x) => src.word(format!("is {}", self.word(x)?.name)),
+                Instr::ActionOf(x) => src.word(format!("action-of {}", self.word(x)?.name)),
+                Instr::String(addr, len) => {
+                    let bytes = self.read_bytes(addr, len)?;
+                    if needs_escape(&bytes) {
+                        src.word(format!("s\\\" {}\"", escape(&bytes)));
+                    } else {
+                        src.word(format!("s\" {}\"", String::from_utf8_lossy(&bytes)));
+                    }
+                }
+                Instr::Print(addr, len) => {
+                    let bytes = self.read_bytes(addr, len)?;
+                    if needs_escape(&bytes) {
+                        src.word(format!("s\\\" {}\" type", escape(&bytes)));
+                    } else {
+                        src.word(format!(".\" {}\"", String::from_utf8_lossy(&bytes)));
+                    }
+                }
+                Instr::CString(addr) => {
+                    let bytes = self.read_bytes(addr + 1, self.fetch(addr)?)?;
+                    src.word(format!("c\" {}\"", String::from_utf8_lossy(&bytes)));
+                }
+                Instr::Call(x) if x == xt => src.word("recurse".to_string()),
+                Instr::Call(x) | Instr::Compile(x) => {
+                    let callee = self.word(x)?;
+                    match code[ip] {
+                        Instr::Compile(_) => src.word(format!("postpone {}", callee.name)),
+                        _ if callee.immediate => src.word(format!("postpone {}", callee.name)),
+                        _ => src.word(callee.name.clone()),
+                    }
+                }
+                Instr::BranchIfZero(end) => {
+                    src.open("if");
+                    control.push(Open::If(end));
+                }
+                Instr::Branch(end) => match control.last() {
+                    Some(&Open::If(e)) if e == ip + 1 => {
+                        control.pop();
+                        src.middle("else");
+                        control.push(Open::Else(end));
+                    }
+                    _ => return Err(format!("Cannot decompile branch in {}", word.name)),
+                },
+                Instr::Locals(..) => match word.locals_decl {
+                    Some(ref decl) => src.word(decl.clone()),
+                    None => src.word("{: :}".to_string()),
+                },
+                Instr::Local(index) => src.word(local_name(word, index)),
+                Instr::ToLocal(index) => src.word(format!("to {}", local_name(word, index))),
+                Instr::PlusToLocal(index) => src.word(format!("+to {}", local_name(word, index))),
+                Instr::EndLocals => {}
+                Instr::Does(..) => src.word("does>".to_string()),
+                Instr::Do(_) => {
+                    src.open("do");
+                    control.push(Open::Do);
+                }
+                Instr::Loop(_) => {
+                    control.pop();
+                    src.close("loop");
+                }
+            }
+            ip += 1;
+        }
+        src.word(";".to_string());
+        src.flush();
+
+        let mut header = format!(": {}", word.name);
+        if let Some(ref effect) = word.stack_effect {
+            header.push_str(&format!(" ( {} )", effect));
+        }
+
+        // Short definitions without control structures fit on one line
+        if src.lines.len() == 1 {
+            return Ok(format!("{} {}", header, src.lines[0].1));
+        }
+
+        let mut text = header;
+        for (indent, line) in src.lines {
+            text.push('\n');
+            text.push_str(&"  ".repeat(indent + 1));
+            text.push_str(&line);
+        }
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use forth::env::ForthEnv;
+    use forth::inter::tests::eval;
+
+    // Source shown by `see` for the named word once the source has run
+    fn see(src: &str, name: &str) -> String {
+        let mut env = ForthEnv::empty();
+        eval(&mut env, src).unwrap();
+        let xt = env.find(name).unwrap();
+        env.decompile(xt).unwrap()
+    }
+
+    #[test]
+    fn control_structures_round_trip() {
+        let src = ": f ( n -- )
+  0 > if
+    1 0 do
+      i .
+    loop
+  else
+    .\" none\"
+  then ;";
+        assert_eq!(see(&src.replace('\n', " "), "f"), src);
+        assert_eq!(
+            see(": g 1 if 2 then ;", "g"),
+            ": g\n  1 if\n    2\n  then ;"
+        );
+    }
+
+    #[test]
+    fn strings_round_trip() {
+        let src = ": s s\" a\" c\" b\" s\\\" \\tc\\q\" ;";
+        assert_eq!(see(src, "s"), src);
+    }
+
+    #[test]
+    fn printed_strings_round_trip() {
+        let src = ": p .\" a  b\" ;";
+        assert_eq!(see(src, "p"), src);
+        assert_eq!(see(": p .\" a\tb\" ;", "p"), ": p s\\\" a\\tb\" type ;");
+    }
+
+    #[test]
+    fn words_show_how_they_are_defined() {
+        assert_eq!(see("5 constant k", "k"), "5 constant k");
+        assert_eq!(see("3 value v", "v"), "3 value v");
+        assert_eq!(see(": r recurse ;", "r"), ": r recurse ;");
+        assert_eq!(see("defer d ' dup is d", "d"), "defer d\n' dup is d");
+        let src = ": t 1 to v ['] k is d action-of d ;";
+        assert_eq!(
+            see(&format!("3 value v 5 constant k defer d\n{}", src), "t"),
+            src
+        );
+    }
+
+    #[test]
+    fn locals_keep_their_declaration() {
+        let src = ": f {: a b | t -- c :} a b + to t 5 +to t t ;";
+        assert_eq!(see(src, "f"), src);
+        let src = ": g locals| x y | x y - ;";
+        assert_eq!(see(src, "g"), src);
+    }
+}
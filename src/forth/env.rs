@@ -1,85 +1,662 @@
 use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
 use std::result;
 
+use columns::{self, LINE_WIDTH};
+
+use forth::compiler::{Compiler, Instr};
+use forth::lexer::Lexer;
+use forth::number::{self, MAX_BASE, MIN_BASE};
+use forth::objects;
+use forth::ops;
+use forth::parsing;
+
 pub(crate) type ForthResult<T> = result::Result<T, String>;
-pub(crate) type Ops = dyn Fn(&mut ForthEnv) -> ForthResult<()>;
-pub(crate) type ForthFunc = (String, Vec<String>);
+pub(crate) type Op = fn(&mut ForthEnv) -> ForthResult<()>;
+
+#[derive(Clone)]
+pub(crate) enum WordKind {
+    Builtin(Op),
+    // Compiled function. Calls to other words are bound when the code is
+    // compiled, so redefining a word leaves its existing callers alone.
+    Function(Rc<Vec<Instr>>),
+    Constant(i32),
+    Variable(i32),
+    // Address of the cell holding the value, which gets pushed when the
+    // word runs
+    Value(i32),
+    // Adds its offset to the address of a structure
+    Field(i32),
+    // Pushes the address of its data space, then runs its action if it has
+    // been given one
+    Created(i32, Option<Action>),
+    // Execution token the deferred word runs, if it has been set
+    Deferred(Option<usize>),
+    // Replaces the first wordlist in the search order with its wordlist
+    Vocabulary(usize),
+    // Forgets itself and everything defined after it
+    Marker(Mark),
+}
+
+// Parts of the dictionary state that a marker restores, besides the words
+// and data space
+#[derive(Clone)]
+pub(crate) struct Mark {
+    wordlists: usize,
+    order: Vec<usize>,
+    current: usize,
+    // Action of each deferred word when the marker was added
+    deferred: Vec<(usize, Option<usize>)>,
+}
+
+// What a created word does after pushing the address of its data space
+#[derive(Clone, Copy)]
+pub(crate) enum Action {
+    // Code of a function from the given position on, set with `does>`
+    Code(usize, usize),
+    // Runs the method the word stands for, as given by the object's class
+    Dispatch,
+    // Adds the offset of the instance variable to the object
+    VarField,
+}
 
-#[derive(Debug, Clone, Eq, PartialEq)]
-pub enum ForthVar {
-    Var(i32),
-    Array(Vec<i32>),
+// Every word gets an entry in the word list, and its index there is the
+// word's execution token
+pub(crate) struct Word {
+    pub(crate) name: String,
+    pub(crate) kind: WordKind,
+    pub(crate) immediate: bool,
+    // Wordlist the word was defined in
+    pub(crate) wordlist: usize,
+    // Data space pointer when the word was added
+    here: i32,
+    // Stack effect comment given at the start of a definition
+    pub(crate) stack_effect: Option<String>,
+    // Names of the locals of a function, by their index in its frame
+    pub(crate) locals: Vec<String>,
+    // Declaration of the locals of a function as it was written
+    pub(crate) locals_decl: Option<String>,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
-pub enum VarRef {
-    Var(String),
-    Array(String, i32),
+// What a word of input stands for, going by the dictionary
+#[derive(Clone, Copy)]
+pub enum WordClass {
+    Builtin,
+    User,
+    Variable,
+    Constant,
+    Number,
+    Unknown,
 }
 
+// Words which can be found by name, newest word with each name only
+pub(crate) struct WordList {
+    name: Option<String>,
+    words: HashMap<String, usize>,
+}
+
+// Size of the region at the start of memory used for pictured numeric output
+pub(crate) const HOLD_SIZE: usize = 128;
+
+// Size of each of the buffers following the hold region, which interpreted
+// string literals are copied into in turn
+const STRING_BUFFER_SIZE: usize = 256;
+const STRING_BUFFERS: usize = 2;
+
+// Address of the cell holding the number base, the first cell of data space
+pub(crate) const BASE_ADDR: i32 = (HOLD_SIZE + STRING_BUFFERS * STRING_BUFFER_SIZE) as i32;
+
+// Largest number of cells data space can grow to
+const MAX_DATA_SPACE: i32 = 1 << 24;
+
+// Address of the cell holding the interpreter state, non-zero when compiling
+pub(crate) const STATE_ADDR: i32 = BASE_ADDR + 1;
+
+// Wordlist holding the builtin words
+pub(crate) const FORTH_WORDLIST: usize = 0;
+
+// Wordlist holding the search order words, which stays in the search order
+// so that it can always be changed back
+pub(crate) const ROOT_WORDLIST: usize = 1;
+
 pub struct ForthEnv {
     stack: Vec<i32>,
-    funcs: HashMap<String, ForthFunc>,
-    vars: HashMap<String, ForthVar>,
-    var_refs: Vec<VarRef>,
-    constants: HashMap<String, i32>,
-    specials: HashMap<String, i32>,
+    words: Vec<Word>,
+    wordlists: Vec<WordList>,
+    // Wordlists searched when looking up a word, in the order they are
+    // searched
+    order: Vec<usize>,
+    // Wordlist new definitions go into
+    current: usize,
+    // Number of builtin words, which cannot be forgotten
+    fence: usize,
+    input: String,
+    input_pos: usize,
+    // Whether the input ended in the middle of a parsing word
+    input_exhausted: bool,
+    // Input kept from a parsing word that ran out of it, to be continued
+    pending: String,
+    loops: Vec<(i32, i32)>,
+    // Number of words being executed inside one another
+    pub(crate) call_depth: usize,
+    // Locals of the functions being run, with the start of each frame
+    locals: Vec<i32>,
+    frames: Vec<usize>,
+    memory: Vec<i32>,
+    // String buffer the next interpreted string literal goes into
+    next_buffer: usize,
+    // Data space pointer once the newest word was defined, below which
+    // data space cannot be freed
+    data_floor: i32,
+    pictured: Vec<u8>,
+    strings: Vec<Option<Vec<u8>>>,
+    compiler: Option<Compiler>,
 }
 
 impl ForthEnv {
     pub fn empty() -> ForthEnv {
-        ForthEnv {
+        let mut env = ForthEnv {
             stack: vec![],
-            funcs: HashMap::new(),
-            vars: HashMap::new(),
-            var_refs: vec![],
-            constants: HashMap::new(),
-            specials: HashMap::new(),
+            words: vec![],
+            wordlists: vec![
+                WordList {
+                    name: Some("forth".to_string()),
+                    words: HashMap::new(),
+                },
+                WordList {
+                    name: Some("root".to_string()),
+                    words: HashMap::new(),
+                },
+            ],
+            order: vec![FORTH_WORDLIST, ROOT_WORDLIST],
+            current: FORTH_WORDLIST,
+            fence: 0,
+            input: String::new(),
+            input_pos: 0,
+            input_exhausted: false,
+            pending: String::new(),
+            loops: vec![],
+            call_depth: 0,
+            locals: vec![],
+            frames: vec![],
+            memory: vec![0; BASE_ADDR as usize],
+            next_buffer: 0,
+            data_floor: 0,
+            pictured: vec![],
+            strings: vec![],
+            compiler: None,
+        };
+
+        let addr = env.add_variable("base");
+        debug_assert_eq!(addr, BASE_ADDR);
+        env.memory[BASE_ADDR as usize] = 10;
+        let addr = env.add_variable("state");
+        debug_assert_eq!(addr, STATE_ADDR);
+        ops::init(&mut env);
+        parsing::init(&mut env);
+        objects::init(&mut env);
+        env.fence = env.words.len();
+        env.data_floor = env.here();
+        env
+    }
+
+    pub fn is_compiling(&self) -> bool {
+        self.memory[STATE_ADDR as usize] != 0
+    }
+
+    pub fn set_compiling(&mut self, compiling: bool) {
+        self.memory[STATE_ADDR as usize] = if compiling { -1 } else { 0 };
+    }
+
+    // Starts compiling a definition (or a control structure outside of one)
+    pub fn begin_compile(&mut self, compiler: Compiler) {
+        self.compiler = Some(compiler);
+        self.set_compiling(true);
+    }
+
+    pub fn compiler(&mut self) -> ForthResult<&mut Compiler> {
+        match self.compiler {
+            Some(ref mut compiler) => Ok(compiler),
+            None => Err("Not compiling a definition".to_string()),
         }
     }
 
-    pub fn top_variable_ref(&mut self) -> Option<VarRef> {
-        if self.var_refs.is_empty() {
-            None
-        } else {
-            let var = self.var_refs[self.var_refs.len() - 1].clone();
-            Some(var)
+    pub fn end_compile(&mut self) -> ForthResult<Compiler> {
+        self.set_compiling(false);
+        match self.compiler.take() {
+            Some(compiler) => Ok(compiler),
+            None => Err("Not compiling a definition".to_string()),
+        }
+    }
+
+    // Throws away a partial definition, for example after an error
+    pub fn abort_compile(&mut self) {
+        if let Some(xt) = self.compiler.take().and_then(|c| c.function().map(|f| f.1)) {
+            if xt + 1 == self.words.len() {
+                self.words.pop();
+            }
+        }
+        self.set_compiling(false);
+    }
+
+    pub fn base(&self) -> ForthResult<u32> {
+        match self.fetch(BASE_ADDR)? {
+            b if b >= MIN_BASE as i32 && b <= MAX_BASE as i32 => Ok(b as u32),
+            b => Err(format!("Invalid number base: {}", b)),
+        }
+    }
+
+    pub fn set_base(&mut self, base: u32) {
+        self.memory[BASE_ADDR as usize] = base as i32;
+    }
+
+    // Makes the text the input source, which words are then read from,
+    // following on from any input kept from before
+    pub fn set_input(&mut self, src: &str) {
+        self.input = mem::take(&mut self.pending);
+        if !self.input.is_empty() {
+            self.input.push('\n');
+        }
+        self.input.push_str(src);
+        self.input_pos = 0;
+        self.input_exhausted = false;
+    }
+
+    pub fn input_pos(&self) -> usize {
+        self.input_pos
+    }
+
+    // Whether a parsing word has run out of input, in which case its error
+    // can be fixed by more input
+    pub fn input_exhausted(&self) -> bool {
+        self.input_exhausted
+    }
+
+    // Error for a parsing word that ran out of input before its terminator
+    pub fn unterminated(&mut self, what: &str) -> String {
+        self.input_exhausted = true;
+        format!("Nonterminated {}", what)
+    }
+
+    // Keeps the input from the position on, to be read again along with the
+    // next input
+    pub fn keep_input(&mut self, from: usize) {
+        self.pending = self.input[from..].to_string();
+    }
+
+    // Whether the input so far is unfinished, either inside a definition or
+    // control structure, or with a parsing word waiting for more input
+    pub fn is_incomplete(&self) -> bool {
+        self.compiler.is_some() || !self.pending.is_empty()
+    }
+
+    // Drops any unfinished definition and kept input
+    pub fn cancel_input(&mut self) {
+        self.abort_compile();
+        self.pending.clear();
+    }
+
+    fn with_input<T, F: FnOnce(&mut Lexer) -> T>(&mut self, f: F) -> T {
+        let mut lexer = Lexer::new(&self.input[self.input_pos..]);
+        let res = f(&mut lexer);
+        self.input_pos += lexer.pos();
+        self.input_exhausted |= lexer.exhausted();
+        res
+    }
+
+    pub fn next_word(&mut self) -> Option<String> {
+        self.with_input(|lexer| lexer.next_word().map(|w| w.to_string()))
+    }
+
+    pub fn parse(&mut self, delim: char) -> Option<String> {
+        self.with_input(|lexer| lexer.parse(delim).map(|s| s.to_string()))
+    }
+
+    pub fn parse_escaped(&mut self) -> ForthResult<Vec<u8>> {
+        self.with_input(|lexer| lexer.parse_escaped())
+    }
+
+    // Adds a word which is hidden until it gets revealed
+    fn add_word(&mut self, name: &str, kind: WordKind) -> usize {
+        self.words.push(Word {
+            name: name.to_string(),
+            kind,
+            immediate: false,
+            wordlist: self.current,
+            here: self.here(),
+            stack_effect: None,
+            locals: vec![],
+            locals_decl: None,
+        });
+        self.words.len() - 1
+    }
+
+    // Makes the word findable by name, shadowing older words with that name
+    // in the same wordlist or found through the search order
+    fn reveal(&mut self, xt: usize) {
+        let name = self.words[xt].name.clone();
+        let wid = self.words[xt].wordlist;
+        if self.shadows(&name, wid) {
+            println!("redefined {}", name);
+        }
+        self.wordlists[wid].words.insert(name, xt);
+        self.data_floor = self.here();
+    }
+
+    fn shadows(&self, name: &str, wid: usize) -> bool {
+        self.find(name).is_some() || self.wordlists[wid].words.contains_key(name)
+    }
+
+    pub fn is_builtin(&self, xt: usize) -> bool {
+        xt < self.fence
+    }
+
+    pub fn word_count(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn word(&self, xt: usize) -> ForthResult<&Word> {
+        match self.words.get(xt) {
+            Some(word) => Ok(word),
+            None => Err(format!("Invalid execution token: {}", xt)),
+        }
+    }
+
+    // Execution token of the word with the given name, taken from the first
+    // wordlist in the search order which has one
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.order
+            .iter()
+            .filter_map(|&wid| self.wordlists[wid].words.get(name))
+            .next()
+            .cloned()
+    }
+
+    pub fn add_wordlist(&mut self, name: Option<&str>) -> usize {
+        self.wordlists.push(WordList {
+            name: name.map(|n| n.to_string()),
+            words: HashMap::new(),
+        });
+        self.wordlists.len() - 1
+    }
+
+    fn wordlist(&self, wid: usize) -> ForthResult<&WordList> {
+        match self.wordlists.get(wid) {
+            Some(wordlist) => Ok(wordlist),
+            None => Err(format!("Invalid wordlist: {}", wid)),
         }
     }
 
-    pub fn pop_variable_ref(&mut self) -> Option<VarRef> {
-        self.var_refs.pop()
+    pub fn search_wordlist(&self, wid: usize, name: &str) -> ForthResult<Option<usize>> {
+        Ok(self.wordlist(wid)?.words.get(name).cloned())
+    }
+
+    pub fn get_order(&self) -> &[usize] {
+        &self.order
+    }
+
+    pub fn set_order(&mut self, order: Vec<usize>) -> ForthResult<()> {
+        for &wid in &order {
+            self.wordlist(wid)?;
+        }
+        self.order = order;
+        Ok(())
     }
 
-    pub fn push_variable_ref(&mut self, var: VarRef) {
-        self.var_refs.push(var);
+    pub fn get_current(&self) -> usize {
+        self.current
     }
 
-    pub fn get_variable(&self, name: &str) -> Option<ForthVar> {
-        self.vars.get(name).cloned()
+    pub fn set_current(&mut self, wid: usize) -> ForthResult<()> {
+        self.wordlist(wid)?;
+        self.current = wid;
+        Ok(())
     }
 
-    pub fn add_variable(&mut self, name: &str, value: ForthVar) -> Option<ForthVar> {
-        self.vars.insert(name.to_string(), value)
+    // Adds a word which puts the wordlist first in the search order
+    pub fn add_vocabulary(&mut self, name: &str) -> usize {
+        let wid = self.add_wordlist(Some(name));
+        let xt = self.add_word(name, WordKind::Vocabulary(wid));
+        self.reveal(xt);
+        xt
     }
 
-    pub fn get_constant(&self, name: &str) -> Option<i32> {
-        self.constants.get(name).cloned()
+    // Adds a word which forgets itself and everything after it, restoring
+    // the search order and deferred words as they are now
+    pub fn add_marker(&mut self, name: &str) -> usize {
+        let deferred = self
+            .words
+            .iter()
+            .enumerate()
+            .filter_map(|(xt, w)| match w.kind {
+                WordKind::Deferred(action) => Some((xt, action)),
+                _ => None,
+            })
+            .collect();
+        let mark = Mark {
+            wordlists: self.wordlists.len(),
+            order: self.order.clone(),
+            current: self.current,
+            deferred,
+        };
+        let xt = self.add_word(name, WordKind::Marker(mark));
+        self.reveal(xt);
+        xt
     }
 
-    pub fn add_constant(&mut self, name: &str, value: i32) -> Option<i32> {
-        self.constants.insert(name.to_string(), value)
+    pub fn restore_mark(&mut self, xt: usize, mark: Mark) -> ForthResult<()> {
+        self.forget(xt)?;
+        self.wordlists.truncate(mark.wordlists);
+        self.order = mark.order;
+        self.current = mark.current;
+        for (xt, action) in mark.deferred {
+            self.words[xt].kind = WordKind::Deferred(action);
+        }
+        Ok(())
     }
 
-    pub fn get_function(&self, name: &str) -> Option<ForthFunc> {
-        match self.funcs.get(name) {
-            Some(func) => Some(func.clone()),
-            None => None,
+    // Removes the word and every word defined after it, along with the data
+    // space allotted since it was added
+    pub fn forget(&mut self, xt: usize) -> ForthResult<()> {
+        if xt < self.fence {
+            return Err(format!(
+                "Cannot forget builtin word {}",
+                self.word(xt)?.name
+            ));
+        }
+
+        let here = self.word(xt)?.here;
+        self.words.truncate(xt);
+        self.memory.truncate(here as usize);
+        self.data_floor = here;
+
+        for wordlist in &mut self.wordlists {
+            wordlist.words.clear();
+        }
+        for (i, word) in self.words.iter_mut().enumerate() {
+            if let WordKind::Deferred(Some(action)) = word.kind {
+                if action >= xt {
+                    word.kind = WordKind::Deferred(None);
+                }
+            }
+            if let Some(wordlist) = self.wordlists.get_mut(word.wordlist) {
+                wordlist.words.insert(word.name.clone(), i);
+            }
         }
+        Ok(())
+    }
+
+    pub fn print_order(&self) {
+        let names: Vec<_> = self
+            .order
+            .iter()
+            .map(|&wid| match self.wordlists[wid].name {
+                Some(ref name) => name.clone(),
+                None => format!("#{}", wid),
+            })
+            .collect();
+        println!("{}", names.join(" "));
     }
 
-    pub fn add_function(&mut self, name: &str, func: ForthFunc) -> Option<ForthFunc> {
-        self.funcs.insert(name.to_string(), func)
+    pub fn add_builtin(&mut self, name: &str, op: Op) -> usize {
+        let xt = self.add_word(name, WordKind::Builtin(op));
+        self.reveal(xt);
+        xt
+    }
+
+    // Adds a builtin to the root wordlist
+    pub fn add_root_builtin(&mut self, name: &str, op: Op) -> usize {
+        let current = mem::replace(&mut self.current, ROOT_WORDLIST);
+        let xt = self.add_builtin(name, op);
+        self.current = current;
+        xt
+    }
+
+    // Adds a builtin which is executed rather than compiled inside a
+    // definition
+    pub fn add_immediate(&mut self, name: &str, op: Op) -> usize {
+        let xt = self.add_builtin(name, op);
+        self.words[xt].immediate = true;
+        xt
+    }
+
+    // Allots a fresh cell for the variable and returns its address
+    pub fn add_variable(&mut self, name: &str) -> i32 {
+        let addr = self.here();
+        let xt = self.add_word(name, WordKind::Variable(addr));
+        self.memory.push(0);
+        self.reveal(xt);
+        addr
+    }
+
+    pub fn add_value(&mut self, name: &str, value: i32) -> usize {
+        let addr = self.here();
+        let xt = self.add_word(name, WordKind::Value(addr));
+        self.memory.push(value);
+        self.reveal(xt);
+        xt
+    }
+
+    pub fn value_addr(&self, xt: usize) -> ForthResult<i32> {
+        match self.word(xt)?.kind {
+            WordKind::Value(addr) => Ok(addr),
+            _ => Err(format!("{} is not a value", self.words[xt].name)),
+        }
+    }
+
+    pub fn add_constant(&mut self, name: &str, value: i32) -> usize {
+        let xt = self.add_word(name, WordKind::Constant(value));
+        self.reveal(xt);
+        xt
+    }
+
+    // Changes the value of a constant, used to fill in the size of a
+    // structure once all its fields are known
+    pub fn set_constant(&mut self, xt: usize, value: i32) -> ForthResult<()> {
+        match self.word(xt)?.kind {
+            WordKind::Constant(_) => {
+                self.words[xt].kind = WordKind::Constant(value);
+                Ok(())
+            }
+            _ => Err(format!("{} is not a constant", self.words[xt].name)),
+        }
+    }
+
+    // Adds a word which pushes the address of the data space following it,
+    // without allotting any
+    pub fn add_created(&mut self, name: &str) -> usize {
+        let addr = self.here();
+        let xt = self.add_word(name, WordKind::Created(addr, None));
+        self.reveal(xt);
+        xt
+    }
+
+    // Sets the action of the most recently created word
+    pub fn set_action(&mut self, action: Action) -> ForthResult<()> {
+        if let Some(word) = self.words.last_mut() {
+            if let WordKind::Created(addr, _) = word.kind {
+                word.kind = WordKind::Created(addr, Some(action));
+                return Ok(());
+            }
+        }
+        Err("does> used without create".to_string())
+    }
+
+    // Address of the data space of a created word
+    pub fn body(&self, xt: usize) -> ForthResult<i32> {
+        match self.word(xt)?.kind {
+            WordKind::Created(addr, _) => Ok(addr),
+            _ => Err(format!(
+                "{} was not created with create",
+                self.words[xt].name
+            )),
+        }
+    }
+
+    pub fn add_field(&mut self, name: &str, offset: i32) -> usize {
+        let xt = self.add_word(name, WordKind::Field(offset));
+        self.reveal(xt);
+        xt
+    }
+
+    // Adds a function without code, which can only be found by name once it
+    // has been defined. This gives a definition an execution token so that
+    // it can refer to itself.
+    pub fn reserve_function(&mut self, name: &str) -> usize {
+        self.add_word(name, WordKind::Function(Rc::new(vec![])))
+    }
+
+    // Defines the function from the compiled code, shadowing any previous
+    // word with that name
+    pub fn define_function(&mut self, xt: usize, comp: Compiler) -> ForthResult<()> {
+        let stack_effect = comp.stack_effect();
+        let locals = comp.locals();
+        let locals_decl = comp.locals_decl();
+        let code = comp.finish()?;
+
+        let word = &mut self.words[xt];
+        word.kind = WordKind::Function(Rc::new(code));
+        word.stack_effect = stack_effect;
+        word.locals = locals;
+        word.locals_decl = locals_decl;
+        self.reveal(xt);
+        Ok(())
+    }
+
+    pub fn add_deferred(&mut self, name: &str) -> usize {
+        let xt = self.add_word(name, WordKind::Deferred(None));
+        self.reveal(xt);
+        xt
+    }
+
+    pub fn defer_get(&self, xt: usize) -> ForthResult<Option<usize>> {
+        match self.word(xt)?.kind {
+            WordKind::Deferred(action) => Ok(action),
+            _ => Err(format!("{} is not a deferred word", self.words[xt].name)),
+        }
+    }
+
+    pub fn defer_set(&mut self, xt: usize, action: usize) -> ForthResult<()> {
+        self.word(action)?;
+        self.defer_get(xt)?;
+        self.words[xt].kind = WordKind::Deferred(Some(action));
+        Ok(())
+    }
+
+    pub fn is_immediate(&self, xt: usize) -> bool {
+        self.words[xt].immediate
+    }
+
+    // Marks the most recent definition as immediate
+    pub fn set_immediate(&mut self) -> ForthResult<()> {
+        match self.words.last_mut() {
+            Some(word) => {
+                word.immediate = true;
+                Ok(())
+            }
+            None => Err("No definition to make immediate".to_string()),
+        }
     }
 
     pub fn pop(&mut self, msg: String) -> ForthResult<i32> {
@@ -100,62 +677,455 @@ impl ForthEnv {
         self.stack.push(val);
     }
 
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.stack.clear();
+    }
+
+    // Copies the nth item (0 is the top) of the stack
+    pub fn pick(&self, n: i32) -> ForthResult<i32> {
+        if n >= 0 && (n as usize) < self.stack.len() {
+            Ok(self.stack[self.stack.len() - 1 - n as usize])
+        } else {
+            Err(format!(
+                "Cannot pick item {} when stack depth is: {}",
+                n,
+                self.stack.len()
+            ))
+        }
+    }
+
+    // Moves the nth item (0 is the top) of the stack to the top
+    pub fn roll(&mut self, n: i32) -> ForthResult<()> {
+        if n >= 0 && (n as usize) < self.stack.len() {
+            let x = self.stack.remove(self.stack.len() - 1 - n as usize);
+            self.stack.push(x);
+            Ok(())
+        } else {
+            Err(format!(
+                "Cannot roll item {} when stack depth is: {}",
+                n,
+                self.stack.len()
+            ))
+        }
+    }
+
+    pub fn print_depth_stack(&self) {
+        let base = self.base().unwrap_or(10);
+        let items: Vec<_> = self
+            .stack
+            .iter()
+            .map(|&n| number::format_number(n, base))
+            .collect();
+        println!("<{}> {}", self.stack.len(), items.join(" "));
+    }
+
+    // Depth of the stack followed by at most the given number of items from
+    // the top
+    pub fn stack_summary(&self, count: usize) -> String {
+        let base = self.base().unwrap_or(10);
+        let skip = self.stack.len().saturating_sub(count);
+        let mut items: Vec<_> = self.stack[skip..]
+            .iter()
+            .map(|&n| number::format_number(n, base))
+            .collect();
+        if skip > 0 {
+            items.insert(0, "..".to_string());
+        }
+        format!("<{}> {}", self.stack.len(), items.join(" "))
+    }
+
     pub fn print_stack(&self) {
-        println!("{:?}", self.stack);
+        let base = self.base().unwrap_or(10);
+        let items: Vec<_> = self
+            .stack
+            .iter()
+            .map(|&n| number::format_number(n, base))
+            .collect();
+        println!("[{}]", items.join(", "));
     }
 
     pub fn print_func(&self) {
-        println!("{:?}", self.funcs);
+        // Functions in definition order, leaving out shadowed ones
+        let names: Vec<_> = self
+            .words
+            .iter()
+            .enumerate()
+            .filter(|&(i, w)| match w.kind {
+                WordKind::Function(_) | WordKind::Deferred(_) => self.find(&w.name) == Some(i),
+                _ => false,
+            })
+            .map(|(_, w)| w.name.as_str())
+            .collect();
+        println!("{}", names.join(" "));
     }
 
+    // Lists the words of each wordlist in the search order, grouped by kind
+    pub fn print_words(&self) {
+        let mut seen = vec![];
+        for &wid in &self.order {
+            if seen.contains(&wid) {
+                continue;
+            }
+            seen.push(wid);
+
+            let wordlist = &self.wordlists[wid];
+            match wordlist.name {
+                Some(ref name) => println!("{}:", name),
+                None => println!("#{}:", wid),
+            }
+
+            let groups = [
+                "functions",
+                "deferred",
+                "variables",
+                "constants",
+                "values",
+                "fields",
+                "created",
+                "vocabularies",
+                "markers",
+                "builtins",
+            ];
+            for (group, title) in groups.iter().enumerate() {
+                let mut names: Vec<_> = wordlist
+                    .words
+                    .values()
+                    .map(|&xt| &self.words[xt])
+                    .filter(|w| Self::word_group(&w.kind) == group)
+                    .map(|w| w.name.as_str())
+                    .collect();
+                if !names.is_empty() {
+                    names.sort();
+                    println!("  {}:", title);
+                    print_columns(&names, 4);
+                }
+            }
+        }
+    }
+
+    pub fn classify(&self, word: &str) -> WordClass {
+        match self.find(word) {
+            Some(xt) => match self.words[xt].kind {
+                WordKind::Variable(_) | WordKind::Value(_) => WordClass::Variable,
+                WordKind::Constant(_) => WordClass::Constant,
+                _ if xt < self.fence => WordClass::Builtin,
+                _ => WordClass::User,
+            },
+            None => match number::parse_number(word, self.base().unwrap_or(10)) {
+                Some(_) => WordClass::Number,
+                None => WordClass::Unknown,
+            },
+        }
+    }
+
+    // Names of the visible words starting with the prefix, limited to the
+    // kind of word expected after the given word
+    pub fn completions(&self, after: Option<&str>, prefix: &str) -> Vec<String> {
+        let wanted = |kind: &WordKind| match after {
+            Some(":") => matches!(*kind, WordKind::Function(_)),
+            Some("variable") => matches!(*kind, WordKind::Variable(_)),
+            Some("constant") => matches!(*kind, WordKind::Constant(_)),
+            Some("value") | Some("to") | Some("+to") => matches!(*kind, WordKind::Value(_)),
+            Some("defer") | Some("is") | Some("action-of") => {
+                matches!(*kind, WordKind::Deferred(_))
+            }
+            Some("vocabulary") => matches!(*kind, WordKind::Vocabulary(_)),
+            _ => true,
+        };
+
+        let mut names: Vec<_> = self
+            .order
+            .iter()
+            .flat_map(|&wid| self.wordlists[wid].words.iter())
+            .filter(|&(name, &xt)| {
+                name.starts_with(prefix)
+                    && self.find(name) == Some(xt)
+                    && wanted(&self.words[xt].kind)
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    fn word_group(kind: &WordKind) -> usize {
+        match *kind {
+            WordKind::Function(_) => 0,
+            WordKind::Deferred(_) => 1,
+            WordKind::Variable(_) => 2,
+            WordKind::Constant(_) => 3,
+            WordKind::Value(_) => 4,
+            WordKind::Field(_) => 5,
+            WordKind::Created(..) => 6,
+            WordKind::Vocabulary(_) => 7,
+            WordKind::Marker(_) => 8,
+            WordKind::Builtin(_) => 9,
+        }
+    }
+
+    // Prints the visible variables sorted by name, one per line
     pub fn print_vars(&self) {
-        println!("{:?}", self.vars);
+        let base = self.base().unwrap_or(10);
+        let mut values: Vec<_> = self
+            .words
+            .iter()
+            .enumerate()
+            .filter_map(|(xt, w)| match w.kind {
+                WordKind::Variable(addr) if self.find(&w.name) == Some(xt) => {
+                    Some((w.name.as_str(), self.fetch(addr).ok()?))
+                }
+                _ => None,
+            })
+            .collect();
+        values.sort();
+        for (name, value) in values {
+            println!("{} = {}", name, number::format_number(value, base));
+        }
     }
 
-    pub fn get_special(&self, name: &str) -> Option<i32> {
-        if self.specials.contains_key(name) {
-            let val = *self.specials.get(name).unwrap();
-            Some(val)
-        } else {
-            None
+    pub fn push_loop(&mut self, index: i32, limit: i32) {
+        self.loops.push((index, limit));
+    }
+
+    pub fn pop_loop(&mut self) {
+        self.loops.pop();
+    }
+
+    // Starts a frame with locals taken from the stack, in the order they
+    // were pushed, followed by zeroed ones
+    pub fn push_frame(&mut self, from_stack: usize, zeroed: usize) -> ForthResult<()> {
+        if self.stack.len() < from_stack {
+            return Err(format!(
+                "Not enough items on stack for {} locals",
+                from_stack
+            ));
         }
+
+        let start = self.stack.len() - from_stack;
+        self.frames.push(self.locals.len());
+        self.locals.extend(self.stack.drain(start..));
+        self.locals.extend((0..zeroed).map(|_| 0));
+        Ok(())
     }
 
-    pub fn set_special(&mut self, name: &str, value: i32) -> Option<i32> {
-        self.specials.insert(name.to_string(), value)
+    pub fn pop_frame(&mut self) {
+        if let Some(start) = self.frames.pop() {
+            self.locals.truncate(start);
+        }
     }
 
-    pub fn clear_special(&mut self, name: &str) -> Option<i32> {
-        self.specials.remove(name)
+    pub fn set_local(&mut self, index: usize, value: i32) -> ForthResult<()> {
+        match self.frames.last() {
+            Some(&start) if start + index < self.locals.len() => {
+                self.locals[start + index] = value;
+                Ok(())
+            }
+            _ => Err("Local used outside of its definition".to_string()),
+        }
     }
 
-    pub fn allot_array(&mut self, name: &str, length: i32) {
-        let arr = ForthVar::Array(vec![0; length as usize]);
-        self.vars.insert(name.to_string(), arr);
+    pub fn clear_frames(&mut self) {
+        self.locals.clear();
+        self.frames.clear();
     }
 
-    pub fn array_set(&mut self, name: &str, pos: i32, value: i32) -> ForthResult<()> {
-        if self.vars.contains_key(name) {
-            let var = self.vars.get(name).unwrap().clone();
-            match var {
-                ForthVar::Var(_) => Err(format!("Variable {} is not an array!", name)),
-                ForthVar::Array(vec) => {
-                    let mut new_vec = vec.clone();
-                    if new_vec.len() > (pos as usize) && pos >= 0 {
-                        new_vec[pos as usize] = value;
-                        self.vars.insert(name.to_string(), ForthVar::Array(new_vec));
-                        Ok(())
-                    } else {
-                        Err(format!(
-                            "Cannot set at position: {} when array length is: {}",
-                            pos,
-                            new_vec.len()
-                        ))
-                    }
-                }
+    pub fn local(&self, index: usize) -> ForthResult<i32> {
+        match self.frames.last() {
+            Some(&start) if start + index < self.locals.len() => Ok(self.locals[start + index]),
+            _ => Err("Local used outside of its definition".to_string()),
+        }
+    }
+
+    pub fn clear_loops(&mut self) {
+        self.loops.clear();
+    }
+
+    // Steps the innermost loop and returns whether it should run again
+    pub fn loop_next(&mut self) -> bool {
+        match self.loops.last_mut() {
+            Some(&mut (ref mut index, limit)) => {
+                *index += 1;
+                *index < limit
             }
+            None => false,
+        }
+    }
+
+    // Index of the loop at the given depth, 0 being the innermost one
+    pub fn loop_index(&self, depth: usize) -> ForthResult<i32> {
+        if depth < self.loops.len() {
+            Ok(self.loops[self.loops.len() - 1 - depth].0)
+        } else {
+            Err("Loop index used outside of a loop".to_string())
+        }
+    }
+
+    pub fn fetch(&self, addr: i32) -> ForthResult<i32> {
+        if addr >= 0 && (addr as usize) < self.memory.len() {
+            Ok(self.memory[addr as usize])
+        } else {
+            Err(format!("Invalid memory address: {}", addr))
+        }
+    }
+
+    pub fn store(&mut self, addr: i32, value: i32) -> ForthResult<()> {
+        if addr >= 0 && (addr as usize) < self.memory.len() {
+            self.memory[addr as usize] = value;
+            Ok(())
         } else {
-            Err(format!("No such array variable: {}", name))
+            Err(format!("Invalid memory address: {}", addr))
+        }
+    }
+
+    // Address of the next free cell in data space
+    pub fn here(&self) -> i32 {
+        self.memory.len() as i32
+    }
+
+    // Grows (or with a negative count shrinks) data space by n cells and
+    // returns the address of the first cell. Data space of existing words
+    // cannot be freed.
+    pub fn allot(&mut self, n: i32) -> ForthResult<i32> {
+        let addr = self.here();
+        let len = addr as i64 + n as i64;
+        if len < i64::from(self.data_floor) || len > i64::from(MAX_DATA_SPACE) {
+            return Err(format!("Cannot allot {} cells", n));
+        }
+        self.memory.resize(len as usize, 0);
+        Ok(addr)
+    }
+
+    pub fn comma(&mut self, value: i32) {
+        self.memory.push(value);
+    }
+
+    // Copies the bytes into fresh data space and returns their address
+    pub fn add_bytes(&mut self, bytes: &[u8]) -> i32 {
+        let addr = self.here();
+        self.memory.extend(bytes.iter().map(|&b| i32::from(b)));
+        addr
+    }
+
+    // Copies the cells into the next string buffer and returns their address,
+    // for string literals which are not compiled into a definition
+    pub fn add_transient(&mut self, cells: &[i32]) -> ForthResult<i32> {
+        if cells.len() > STRING_BUFFER_SIZE {
+            return Err(format!(
+                "String longer than {} characters",
+                STRING_BUFFER_SIZE
+            ));
+        }
+        let addr = HOLD_SIZE + self.next_buffer * STRING_BUFFER_SIZE;
+        self.next_buffer = (self.next_buffer + 1) % STRING_BUFFERS;
+        self.memory[addr..addr + cells.len()].copy_from_slice(cells);
+        Ok(addr as i32)
+    }
+
+    pub fn read_bytes(&self, addr: i32, len: i32) -> ForthResult<Vec<u8>> {
+        if len < 0 {
+            return Err(format!("Invalid string length: {}", len));
         }
+        let end = addr
+            .checked_add(len)
+            .ok_or_else(|| format!("Invalid memory address: {} + {}", addr, len))?;
+        (addr..end)
+            .map(|a| self.fetch(a).map(|c| c as u8))
+            .collect()
+    }
+
+    pub fn hold_start(&mut self) {
+        self.pictured.clear();
+    }
+
+    pub fn hold(&mut self, c: u8) -> ForthResult<()> {
+        if self.pictured.len() >= HOLD_SIZE {
+            return Err("Pictured numeric output overflow".to_string());
+        }
+        self.pictured.insert(0, c);
+        Ok(())
+    }
+
+    // Copies the pictured output into the hold region and returns its length
+    pub fn hold_end(&mut self) -> i32 {
+        for (i, &c) in self.pictured.iter().enumerate() {
+            self.memory[i] = i32::from(c);
+        }
+        self.pictured.len() as i32
+    }
+
+    // Allocates a new dynamic string and returns its handle. Handles start
+    // at 1 so that 0 is never a valid handle.
+    pub fn str_new(&mut self, bytes: Vec<u8>) -> i32 {
+        match self.strings.iter().position(|s| s.is_none()) {
+            Some(i) => {
+                self.strings[i] = Some(bytes);
+                i as i32 + 1
+            }
+            None => {
+                self.strings.push(Some(bytes));
+                self.strings.len() as i32
+            }
+        }
+    }
+
+    pub fn str_get(&self, handle: i32) -> ForthResult<&Vec<u8>> {
+        match self.strings.get((handle as usize).wrapping_sub(1)) {
+            Some(Some(bytes)) if handle > 0 => Ok(bytes),
+            _ => Err(format!("Invalid string handle: {}", handle)),
+        }
+    }
+
+    pub fn str_get_mut(&mut self, handle: i32) -> ForthResult<&mut Vec<u8>> {
+        match self.strings.get_mut((handle as usize).wrapping_sub(1)) {
+            Some(Some(bytes)) if handle > 0 => Ok(bytes),
+            _ => Err(format!("Invalid string handle: {}", handle)),
+        }
+    }
+
+    pub fn str_free(&mut self, handle: i32) -> ForthResult<()> {
+        self.str_get(handle)?;
+        self.strings[handle as usize - 1] = None;
+        Ok(())
+    }
+}
+
+// Prints the names in as many columns as fit in the line width
+fn print_columns(names: &[&str], indent: usize) {
+    for row in columns::rows(names, LINE_WIDTH - indent) {
+        println!("{}{}", " ".repeat(indent), row);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ForthEnv;
+    use forth::inter::tests::{eval, run};
+
+    #[test]
+    fn definitions_shadow_words_in_the_search_order() {
+        let mut env = ForthEnv::empty();
+        eval(&mut env, "vocabulary v also v definitions : f ;").unwrap();
+        let current = env.get_current();
+        assert!(env.shadows("dup", current));
+        assert!(env.shadows("f", current));
+        assert!(!env.shadows("g", current));
+
+        eval(&mut env, "previous").unwrap();
+        assert!(env.shadows("f", current));
+    }
+
+    #[test]
+    fn markers_restore_deferred_actions() {
+        let src = ": a 1 ; defer d ' a is d marker m : b 2 ; ' b is d m d";
+        assert_eq!(run(src), Ok(vec![1]));
+        let src = "defer d marker m ' dup is d m d";
+        assert_eq!(run(src), Err("Deferred word d has no action".to_string()));
+        let src = ": a 1 ; defer d : b 2 ; ' b is d forget b d";
+        assert_eq!(run(src), Err("Deferred word d has no action".to_string()));
     }
 }
@@ -0,0 +1,268 @@
+use std::mem;
+use std::rc::Rc;
+
+use forth::compiler::Instr;
+use forth::env::{Action, ForthEnv, ForthResult, WordKind};
+use forth::objects;
+
+// Most words executing inside one another through builtins such as
+// `execute`, as each of those nests on the native stack
+const MAX_CALL_DEPTH: usize = 1000;
+
+// Most compiled functions calling one another, kept on the return stack of
+// the inner interpreter
+const MAX_RETURN_DEPTH: usize = 100_000;
+
+// The inner interpreter, which runs execution tokens and compiled code
+impl ForthEnv {
+    pub fn execute(&mut self, xt: usize) -> ForthResult<()> {
+        if self.call_depth >= MAX_CALL_DEPTH {
+            return Err("Return stack overflow".to_string());
+        }
+        self.call_depth += 1;
+        let res = self.execute_word(xt);
+        self.call_depth -= 1;
+        res
+    }
+
+    fn execute_word(&mut self, xt: usize) -> ForthResult<()> {
+        match self.word(xt)?.kind.clone() {
+            WordKind::Builtin(op) => op(self),
+            WordKind::Function(code) => self.run_from(code, 0),
+            WordKind::Constant(x) | WordKind::Variable(x) => {
+                self.push(x);
+                Ok(())
+            }
+            WordKind::Created(addr, action) => {
+                self.push(addr);
+                match action {
+                    Some(Action::Code(func, pos)) => {
+                        let code = self.function_code(func)?;
+                        self.run_from(code, pos)
+                    }
+                    Some(Action::Dispatch) => objects::dispatch(self),
+                    Some(Action::VarField) => objects::var_field(self),
+                    None => Ok(()),
+                }
+            }
+            WordKind::Field(offset) => {
+                let addr = self.pop(format!("Empty stack for field {}", self.word(xt)?.name))?;
+                self.push(addr.wrapping_add(offset));
+                Ok(())
+            }
+            WordKind::Value(addr) => {
+                let x = self.fetch(addr)?;
+                self.push(x);
+                Ok(())
+            }
+            WordKind::Deferred(Some(action)) => self.execute(action),
+            WordKind::Vocabulary(wid) => {
+                let mut order = self.get_order().to_vec();
+                match order.first_mut() {
+                    Some(first) => *first = wid,
+                    None => order.push(wid),
+                }
+                self.set_order(order)
+            }
+            WordKind::Marker(mark) => self.restore_mark(xt, mark),
+            WordKind::Deferred(None) => Err(format!(
+                "Deferred word {} has no action",
+                self.word(xt)?.name
+            )),
+        }
+    }
+
+    fn function_code(&self, func: usize) -> ForthResult<Rc<Vec<Instr>>> {
+        match self.word(func)?.kind {
+            WordKind::Function(ref code) => Ok(code.clone()),
+            _ => Err(format!("{} is not a function", self.word(func)?.name)),
+        }
+    }
+
+    // Code to continue with for a call to the word and the position in it,
+    // if the word is compiled. Other words are executed right away.
+    fn enter(&mut self, xt: usize) -> ForthResult<Option<(Rc<Vec<Instr>>, usize)>> {
+        let mut xt = xt;
+        // A deferred word can only refer to itself through a cycle of
+        // deferred words, which would never reach any code
+        for _ in 0..self.word_count() {
+            match self.word(xt)?.kind.clone() {
+                WordKind::Function(code) => return Ok(Some((code, 0))),
+                WordKind::Created(addr, Some(Action::Code(func, pos))) => {
+                    self.push(addr);
+                    return Ok(Some((self.function_code(func)?, pos)));
+                }
+                WordKind::Deferred(Some(action)) => xt = action,
+                _ => {
+                    self.execute(xt)?;
+                    return Ok(None);
+                }
+            }
+        }
+        Err("Return stack overflow".to_string())
+    }
+
+    pub fn run(&mut self, code: &[Instr]) -> ForthResult<()> {
+        self.run_from(Rc::new(code.to_vec()), 0)
+    }
+
+    // Runs the code from the position on. Calls to compiled functions keep
+    // the code and position to return to on a return stack rather than
+    // nesting on the native stack.
+    pub fn run_from(&mut self, code: Rc<Vec<Instr>>, start: usize) -> ForthResult<()> {
+        let mut code = code;
+        let mut ip = start;
+        let mut returns: Vec<(Rc<Vec<Instr>>, usize)> = vec![];
+
+        loop {
+            if ip >= code.len() {
+                match returns.pop() {
+                    Some((caller, pos)) => {
+                        code = caller;
+                        ip = pos;
+                        continue;
+                    }
+                    None => return Ok(()),
+                }
+            }
+            let instr = code[ip];
+            ip += 1;
+
+            match instr {
+                Instr::Builtin(op, _) => op(self)?,
+                Instr::Literal(n) => self.push(n),
+                Instr::Xt(xt) => self.push(xt as i32),
+                Instr::String(addr, len) => {
+                    self.push(addr);
+                    self.push(len);
+                }
+                Instr::CString(addr) => self.push(addr),
+                Instr::Print(addr, len) => {
+                    let bytes = self.read_bytes(addr, len)?;
+                    print!("{}", String::from_utf8_lossy(&bytes));
+                }
+                Instr::Call(xt) => {
+                    if let Some((callee, pos)) = self.enter(xt)? {
+                        if returns.len() >= MAX_RETURN_DEPTH {
+                            return Err("Return stack overflow".to_string());
+                        }
+                        returns.push((mem::replace(&mut code, callee), ip));
+                        ip = pos;
+                    }
+                }
+                Instr::Branch(target) => ip = target,
+                Instr::BranchIfZero(target) => {
+                    if self.pop("Empty stack for condition in if".to_string())? == 0 {
+                        ip = target;
+                    }
+                }
+                Instr::Do(target) => {
+                    let start = self.pop("Empty stack for start of do loop".to_string())?;
+                    let end = self.pop("Empty stack for end of do loop".to_string())?;
+                    if start < end {
+                        self.push_loop(start, end);
+                    } else {
+                        ip = target;
+                    }
+                }
+                Instr::Loop(target) => {
+                    if self.loop_next() {
+                        ip = target;
+                    } else {
+                        self.pop_loop();
+                    }
+                }
+                Instr::Compile(xt) => self.compile_xt(xt)?,
+                Instr::Locals(from_stack, zeroed) => self.push_frame(from_stack, zeroed)?,
+                Instr::Local(index) => {
+                    let x = self.local(index)?;
+                    self.push(x);
+                }
+                Instr::ToLocal(index) => {
+                    let x = self.pop("Empty stack for to".to_string())?;
+                    self.set_local(index, x)?;
+                }
+                Instr::PlusToLocal(index) => {
+                    let x = self.pop("Empty stack for +to".to_string())?;
+                    let old = self.local(index)?;
+                    self.set_local(index, old.wrapping_add(x))?;
+                }
+                Instr::ToValue(xt) => {
+                    let x = self.pop("Empty stack for to".to_string())?;
+                    self.store(self.value_addr(xt)?, x)?;
+                }
+                Instr::PlusToValue(xt) => {
+                    let x = self.pop("Empty stack for +to".to_string())?;
+                    let addr = self.value_addr(xt)?;
+                    let old = self.fetch(addr)?;
+                    self.store(addr, old.wrapping_add(x))?;
+                }
+                Instr::Is(xt) => {
+                    let action = self.pop("Empty stack for is".to_string())?;
+                    self.defer_set(xt, action as usize)?;
+                }
+                Instr::ActionOf(xt) => match self.defer_get(xt)? {
+                    Some(action) => self.push(action as i32),
+                    None => {
+                        return Err(format!(
+                            "Deferred word {} has no action",
+                            self.word(xt)?.name
+                        ))
+                    }
+                },
+                Instr::EndLocals => self.pop_frame(),
+                // Returns from the function
+                Instr::Does(func, pos) => {
+                    self.set_action(Action::Code(func, pos))?;
+                    ip = code.len();
+                }
+            }
+        }
+    }
+
+    // Adds the word to the definition being compiled
+    pub fn compile_xt(&mut self, xt: usize) -> ForthResult<()> {
+        let instr = match self.word(xt)?.kind {
+            WordKind::Builtin(op) => Instr::Builtin(op, xt),
+            _ => Instr::Call(xt),
+        };
+        self.compiler()?.emit(instr);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use forth::inter::tests::run;
+    use std::thread;
+
+    // Runs the source on a thread with a stack as large as the main one, for
+    // running words nested up to the limit
+    fn run_deep(src: &'static str) -> Result<Vec<i32>, String> {
+        thread::Builder::new()
+            .stack_size(8 << 20)
+            .spawn(move || run(src))
+            .unwrap()
+            .join()
+            .unwrap()
+    }
+
+    #[test]
+    fn deep_recursion_uses_the_return_stack() {
+        let src = ": d 1 - dup if recurse then ; 50000 d";
+        assert_eq!(run(src), Ok(vec![0]));
+        let src = "defer e : o 1 - dup if e then ; ' o is e 50000 o";
+        assert_eq!(run(src), Ok(vec![0]));
+        let src = ": c create , does> @ 1 + ; 3 c x : y x x + ; y";
+        assert_eq!(run(src), Ok(vec![8]));
+    }
+
+    #[test]
+    fn deep_recursion_overflows() {
+        let overflow = Err("Return stack overflow".to_string());
+        assert_eq!(run(": r recurse ; r"), overflow);
+        assert_eq!(run("defer x : y x ; ' y is x y"), overflow);
+        assert_eq!(run("defer x ' x is x x"), overflow);
+        assert_eq!(run_deep("defer x : y ['] x execute ; ' y is x y"), overflow);
+    }
+}
@@ -0,0 +1,200 @@
+// Stack effect and description of the builtin word, for `:help`
+pub fn describe(name: &str) -> Option<&'static str> {
+    let text = match name {
+        // Arithmetic and logic
+        "+" => "( a b -- a+b ) add",
+        "-" => "( a b -- a-b ) subtract",
+        "*" => "( a b -- a*b ) multiply",
+        "/" => "( a b -- a/b ) divide",
+        "mod" => "( a b -- a%b ) remainder of division",
+        "abs" => "( n -- |n| ) absolute value",
+        "and" => "( a b -- a&b ) bitwise and",
+        "or" => "( a b -- a|b ) bitwise or",
+        "invert" => "( x -- ~x ) flip every bit",
+        "=" => "( a b -- flag ) true if equal",
+        "!=" => "( a b -- flag ) true if not equal",
+        "<" => "( a b -- flag ) true if a is less than b",
+        ">" => "( a b -- flag ) true if a is greater than b",
+        "<=" => "( a b -- flag ) true if a is at most b",
+        ">=" => "( a b -- flag ) true if a is at least b",
+
+        // Stack
+        "dup" => "( x -- x x ) duplicate the top item",
+        "drop" => "( x -- ) discard the top item",
+        "swap" => "( a b -- b a ) swap the top two items",
+        "over" => "( a b -- a b a ) copy the second item to the top",
+        "rot" => "( a b c -- b c a ) rotate the third item to the top",
+        "-rot" => "( a b c -- c a b ) rotate the top item to third",
+        "nip" => "( a b -- b ) discard the second item",
+        "tuck" => "( a b -- b a b ) copy the top item below the second",
+        "?dup" => "( x -- x x | 0 ) duplicate the top item unless it is zero",
+        "pick" => "( xu ... x0 u -- xu ... x0 xu ) copy the u-th item to the top",
+        "roll" => "( xu ... x0 u -- xu-1 ... x0 xu ) move the u-th item to the top",
+        "depth" => "( -- n ) number of items on the stack",
+        "clear" => "( ... -- ) empty the stack",
+        "." => "( n -- ) print the top item",
+        ".s" => "( -- ) print the stack with its depth",
+        "u." => "( u -- ) print the top item as unsigned",
+        ".r" => "( n width -- ) print right aligned in the width",
+        "u.r" => "( u width -- ) print unsigned, right aligned in the width",
+
+        // Output
+        "emit" => "( char -- ) print the character",
+        "xemit" => "( xchar -- ) print the extended character",
+        "cr" => "( -- ) start a new line",
+        "type" => "( addr len -- ) print the string",
+        "words" => "( -- ) list the words in the search order",
+
+        // Memory
+        "@" => "( addr -- x ) fetch the cell",
+        "!" => "( x addr -- ) store into the cell",
+        "c@" => "( addr -- char ) fetch the character",
+        "c!" => "( char addr -- ) store the character",
+        "," => "( x -- ) compile the cell into data space",
+        "c," => "( char -- ) compile the character into data space",
+        "move" => "( src dest n -- ) copy n cells",
+        "here" => "( -- addr ) next free address of data space",
+        "allot" => "( n -- ) reserve n cells of data space",
+        "cells" => "( n -- n ) size of n cells",
+        "chars" => "( n -- n ) size of n characters",
+        "base" => "( -- addr ) variable holding the number base",
+        "state" => "( -- addr ) variable which is true while compiling",
+        "hex" => "( -- ) use base 16",
+        "decimal" => "( -- ) use base 10",
+        "binary" => "( -- ) use base 2",
+
+        // Strings
+        "count" => "( c-addr -- addr len ) string of a counted string",
+        "compare" => "( addr1 len1 addr2 len2 -- n ) -1, 0 or 1 as the strings sort",
+        "search" => "( addr1 len1 addr2 len2 -- addr3 len3 flag ) find the second string",
+        "/string" => "( addr len n -- addr+n len-n ) skip n characters",
+        "xc-size" => "( xchar -- n ) bytes taken by the extended character",
+        "xc@+" => "( addr -- addr' xchar ) fetch an extended character and step past it",
+        "xc!+" => "( xchar addr -- addr' ) store an extended character and step past it",
+        "xchar+" => "( addr -- addr' ) step past an extended character",
+        "xchar-" => "( addr -- addr' ) step back over an extended character",
+        "x-size" => "( addr len -- n ) bytes taken by the first extended character",
+        "+x/string" => "( addr len -- addr' len' ) skip the first extended character",
+        "x\\string-" => "( addr len -- addr len' ) drop the last extended character",
+        "x-width" => "( addr len -- n ) number of extended characters",
+        "str-new" => "( -- h ) new empty growable string",
+        "str-free" => "( h -- ) free the growable string",
+        "str-append" => "( h addr len -- ) append the string",
+        "str-append-char" => "( h char -- ) append the character",
+        "str-append-str" => "( h1 h2 -- ) append the second growable string to the first",
+        "str-len" => "( h -- len ) length in bytes",
+        "str-clear" => "( h -- ) empty the growable string",
+        "str-type" => "( h -- ) print the growable string",
+        "str>number" => "( h -- n true | false ) parse the string as a number",
+        "number>str" => "( n -- h ) new growable string holding the number",
+        "s\"" => "( \"text\" -- addr len ) string literal",
+        "s\\\"" => "( \"text\" -- addr len ) string literal with backslash escapes",
+        "c\"" => "( \"text\" -- c-addr ) counted string literal",
+        ".\"" => "( \"text\" -- ) print the text",
+
+        // Pictured numeric output
+        "<#" => "( -- ) start pictured numeric output",
+        "#" => "( ud -- ud' ) add the next digit",
+        "#s" => "( ud -- 0 0 ) add all the remaining digits",
+        "hold" => "( char -- ) add the character",
+        "xhold" => "( xchar -- ) add the extended character",
+        "sign" => "( n -- ) add a minus sign if n is negative",
+        "#>" => "( ud -- addr len ) end pictured numeric output",
+
+        // Definitions
+        ":" => "( \"name\" -- ) start defining a function",
+        ";" => "( -- ) end the definition",
+        "variable" => "( \"name\" -- ) define a variable",
+        "constant" => "( x \"name\" -- ) define a constant",
+        "value" => "( x \"name\" -- ) define a value",
+        "to" => "( x \"name\" -- ) store into the value or local",
+        "+to" => "( n \"name\" -- ) add to the value or local",
+        "create" => "( \"name\" -- ) define a word pushing its data address",
+        "does>" => "( -- ) make the rest of the definition the last created word's action",
+        ">body" => "( xt -- addr ) data address of a created word",
+        "defer" => "( \"name\" -- ) define a word whose action is set later",
+        "is" => "( xt \"name\" -- ) set the action of the deferred word",
+        "action-of" => "( \"name\" -- xt ) action of the deferred word",
+        "defer!" => "( xt2 xt1 -- ) set the action of the deferred word xt1",
+        "defer@" => "( xt1 -- xt2 ) action of the deferred word xt1",
+        "marker" => "( \"name\" -- ) define a word forgetting everything from itself on",
+        "forget" => "( \"name\" -- ) remove the word and everything after it",
+        "immediate" => "( -- ) make the last definition run while compiling",
+        "begin-structure" => "( \"name\" -- struct 0 ) start a structure",
+        "end-structure" => "( struct size -- ) end the structure, setting its size",
+        "+field" => "( offset size \"name\" -- offset' ) define a field of the size",
+        "field:" => "( offset \"name\" -- offset' ) define a cell field",
+        "cfield:" => "( offset \"name\" -- offset' ) define a character field",
+        "{:" => "( ... \"a b | c -- d :}\" -- ) declare locals",
+        "locals|" => "( ... \"a b |\" -- ) declare locals, the first taken from the top",
+
+        // Compiling
+        "[" => "( -- ) interpret inside a definition",
+        "]" => "( -- ) go back to compiling",
+        "literal" => "( x -- ) compile the number",
+        "postpone" => "( \"name\" -- ) compile the compilation behaviour of the word",
+        "compile," => "( xt -- ) compile the word",
+        "recurse" => "( -- ) call the function being defined",
+        "if" => "( flag -- ) run up to else or then only if flag is true",
+        "else" => "( -- ) start the part run when the if flag is false",
+        "then" => "( -- ) end the if",
+        "do" => "( limit start -- ) loop from start up to limit",
+        "loop" => "( -- ) end the do loop",
+        "i" => "( -- n ) index of the innermost loop",
+        "j" => "( -- n ) index of the next outer loop",
+        "(" => "( \"text)\" -- ) comment, giving a definition's stack effect",
+        "\\" => "( \"text\" -- ) comment to the end of the line",
+
+        // Execution tokens
+        "'" => "( \"name\" -- xt ) execution token of the word",
+        "[']" => "( \"name\" -- ) compile the execution token of the word",
+        "execute" => "( xt -- ) run the word",
+        "see" => "( \"name\" -- ) show the source of the word",
+
+        // Search order
+        "wordlist" => "( -- wid ) new wordlist",
+        "forth-wordlist" => "( -- wid ) wordlist of the builtin words",
+        "search-wordlist" => "( addr len wid -- 0 | xt 1 | xt -1 ) find the word in the wordlist",
+        "get-order" => "( -- widn ... wid1 n ) search order",
+        "set-order" => "( widn ... wid1 n -- ) set the search order",
+        "get-current" => "( -- wid ) wordlist new definitions go into",
+        "set-current" => "( wid -- ) make new definitions go into the wordlist",
+        "definitions" => "( -- ) make new definitions go into the first wordlist searched",
+        "forth" => "( -- ) search the forth wordlist first",
+        "only" => "( -- ) search only the root wordlist",
+        "also" => "( -- ) duplicate the first wordlist searched",
+        "previous" => "( -- ) drop the first wordlist searched",
+        "order" => "( -- ) print the search order",
+        "vocabulary" => "( \"name\" -- ) define a word searching its own wordlist first",
+
+        // Objects
+        "object" => "( -- class ) root class",
+        "class" => "( parent -- parent methods vars ) start a class",
+        "method" => "( methods vars \"name\" -- methods' vars ) declare a method",
+        "var" => "( vars size \"name\" -- vars' ) declare an instance variable",
+        "end-class" => "( parent methods vars \"name\" -- ) define the class",
+        "defines" => "( xt class \"method\" -- ) implement the method for the class",
+        "new" => "( class -- object ) new instance of the class",
+        "::" => "( class \"method\" -- ) compile the class's method directly",
+
+        _ => return None,
+    };
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::describe;
+    use forth::env::ForthEnv;
+
+    #[test]
+    fn every_builtin_is_described() {
+        let env = ForthEnv::empty();
+        let missing: Vec<_> = env
+            .completions(None, "")
+            .into_iter()
+            .filter(|name| describe(name).is_none())
+            .collect();
+        assert!(missing.is_empty(), "no help for {:?}", missing);
+    }
+}
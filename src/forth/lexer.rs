@@ -0,0 +1,167 @@
+use forth::env::ForthResult;
+
+// Splits source text into whitespace separated words while keeping the raw
+// text around, so that string literals can be read verbatim
+pub struct Lexer<'a> {
+    src: &'a str,
+    pos: usize,
+    exhausted: bool,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(src: &'a str) -> Self {
+        Lexer {
+            src,
+            pos: 0,
+            exhausted: false,
+        }
+    }
+
+    // Number of bytes of the source read so far
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    // Whether the source ran out before the delimiter being parsed for, so
+    // that more input could complete it
+    pub fn exhausted(&self) -> bool {
+        self.exhausted
+    }
+
+    // Reads the next word along with the single delimiter following it
+    pub fn next_word(&mut self) -> Option<&'a str> {
+        let rest = &self.src[self.pos..];
+        let start = self.pos + rest.len() - rest.trim_start().len();
+        let rest = &self.src[start..];
+        let len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+
+        self.pos = start + len;
+        if let Some(c) = self.src[self.pos..].chars().next() {
+            self.pos += c.len_utf8();
+        }
+
+        if len == 0 {
+            None
+        } else {
+            Some(&self.src[start..start + len])
+        }
+    }
+
+    // Reads the raw text up to the delimiter, which is consumed but not
+    // included in the result
+    pub fn parse(&mut self, delim: char) -> Option<&'a str> {
+        let rest = &self.src[self.pos..];
+        match rest.find(delim) {
+            Some(len) => {
+                self.pos += len + delim.len_utf8();
+                Some(&rest[..len])
+            }
+            None => {
+                self.pos = self.src.len();
+                self.exhausted = true;
+                None
+            }
+        }
+    }
+
+    // Reads a string up to the closing quote, translating backslash escapes
+    pub fn parse_escaped(&mut self) -> ForthResult<Vec<u8>> {
+        let mut bytes = vec![];
+        let mut chars = self.src[self.pos..].char_indices();
+
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(bytes);
+                }
+                '\\' => {
+                    let escaped = match chars.next() {
+                        Some((_, e)) => e,
+                        None => break,
+                    };
+                    match escaped {
+                        'n' => bytes.push(b'\n'),
+                        't' => bytes.push(b'\t'),
+                        'r' => bytes.push(b'\r'),
+                        'a' => bytes.push(7),
+                        'b' => bytes.push(8),
+                        'e' => bytes.push(27),
+                        'f' => bytes.push(12),
+                        'z' | '0' => bytes.push(0),
+                        'q' | '"' => bytes.push(b'"'),
+                        '\\' => bytes.push(b'\\'),
+                        'x' => {
+                            let hex: String = chars.by_ref().take(2).map(|(_, h)| h).collect();
+                            match u8::from_str_radix(&hex, 16) {
+                                Ok(b) if hex.len() == 2 => bytes.push(b),
+                                _ => return Err(format!("Invalid escape: \\x{}", hex)),
+                            }
+                        }
+                        e => return Err(format!("Invalid escape: \\{}", e)),
+                    }
+                }
+                c => {
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+            }
+        }
+
+        self.pos = self.src.len();
+        self.exhausted = true;
+        Err("Nonterminated string".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Lexer;
+
+    #[test]
+    fn splits_words() {
+        let mut lexer = Lexer::new("  1 dup\t+\n.");
+        let words: Vec<_> = (0..5).map(|_| lexer.next_word()).collect();
+        assert_eq!(words, [Some("1"), Some("dup"), Some("+"), Some("."), None]);
+    }
+
+    #[test]
+    fn parses_up_to_the_delimiter() {
+        let mut lexer = Lexer::new(".\" hi there\" x");
+        lexer.next_word();
+        assert_eq!(lexer.parse('"'), Some("hi there"));
+        assert_eq!(lexer.next_word(), Some("x"));
+        assert!(!lexer.exhausted());
+
+        assert_eq!(lexer.parse(')'), None);
+        assert!(lexer.exhausted());
+    }
+
+    #[test]
+    fn translates_escapes() {
+        let mut lexer = Lexer::new(r#"a\tb\n\q\"\\\x41\z" rest"#);
+        assert_eq!(lexer.parse_escaped(), Ok(b"a\tb\n\"\"\\A\0".to_vec()));
+        assert_eq!(lexer.next_word(), Some("rest"));
+    }
+
+    #[test]
+    fn rejects_bad_escapes() {
+        let mut lexer = Lexer::new(r#"\y""#);
+        assert_eq!(
+            lexer.parse_escaped(),
+            Err("Invalid escape: \\y".to_string())
+        );
+        let mut lexer = Lexer::new(r#"\x4""#);
+        assert!(lexer.parse_escaped().is_err());
+        assert!(!lexer.exhausted());
+    }
+
+    #[test]
+    fn reports_unterminated_strings() {
+        for src in &["abc", r#"abc\"#, r#"abc\""#] {
+            let mut lexer = Lexer::new(src);
+            assert!(lexer.parse_escaped().is_err());
+            assert!(lexer.exhausted(), "{}", src);
+        }
+    }
+}
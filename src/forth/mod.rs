@@ -1,7 +1,15 @@
+mod compiler;
+mod decompile;
 pub mod env;
+mod exec;
+pub mod help;
 pub mod inter;
+mod lexer;
+mod number;
+mod objects;
 mod ops;
+mod parsing;
 
 fn valid_forth_name(name: &str) -> bool {
-    name.parse::<i32>().is_err()
+    number::parse_number(name, 10).is_none()
 }
@@ -0,0 +1,123 @@
+// Number parsing and formatting in an arbitrary base
+
+pub const MIN_BASE: u32 = 2;
+pub const MAX_BASE: u32 = 36;
+
+// Parses a number literal in the given base. Besides plain digits the
+// Forth 2012 prefixes are understood: `#` decimal, `$` hex, `%` binary and
+// `'c'` for a character literal. A `-` sign may follow the prefix.
+pub fn parse_number(tok: &str, base: u32) -> Option<i32> {
+    let chars: Vec<char> = tok.chars().collect();
+    if chars.len() == 3 && chars[0] == '\'' && chars[2] == '\'' {
+        return Some(chars[1] as i32);
+    }
+
+    let (base, digits) = match chars.first() {
+        Some('#') => (10, &chars[1..]),
+        Some('$') => (16, &chars[1..]),
+        Some('%') => (2, &chars[1..]),
+        _ => (base, &chars[..]),
+    };
+
+    let (negative, digits) = match digits.first() {
+        Some('-') => (true, &digits[1..]),
+        _ => (false, digits),
+    };
+
+    if digits.is_empty() {
+        return None;
+    }
+
+    let mut value: u64 = 0;
+    for c in digits {
+        let d = c.to_digit(base)?;
+        value = value * u64::from(base) + u64::from(d);
+        if value > u64::from(u32::MAX) {
+            return None;
+        }
+    }
+
+    let value = value as u32 as i32;
+    if negative {
+        Some(value.wrapping_neg())
+    } else {
+        Some(value)
+    }
+}
+
+// Formats an unsigned number in the given base, using upper case digits
+pub fn format_unsigned(num: u32, base: u32) -> String {
+    let mut digits = vec![];
+    let mut n = num;
+    loop {
+        digits.push(digit_char(n % base));
+        n /= base;
+        if n == 0 {
+            break;
+        }
+    }
+    digits.iter().rev().collect()
+}
+
+pub fn format_number(num: i32, base: u32) -> String {
+    if num < 0 {
+        format!("-{}", format_unsigned(num.unsigned_abs(), base))
+    } else {
+        format_unsigned(num as u32, base)
+    }
+}
+
+pub fn digit_char(d: u32) -> char {
+    std::char::from_digit(d, MAX_BASE)
+        .unwrap()
+        .to_ascii_uppercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_number, parse_number};
+
+    #[test]
+    fn parses_in_the_base() {
+        assert_eq!(parse_number("42", 10), Some(42));
+        assert_eq!(parse_number("-42", 10), Some(-42));
+        assert_eq!(parse_number("ff", 16), Some(255));
+        assert_eq!(parse_number("FF", 16), Some(255));
+        assert_eq!(parse_number("101", 2), Some(5));
+        assert_eq!(parse_number("z", 36), Some(35));
+    }
+
+    #[test]
+    fn parses_prefixes() {
+        assert_eq!(parse_number("#10", 16), Some(10));
+        assert_eq!(parse_number("$10", 10), Some(16));
+        assert_eq!(parse_number("%10", 10), Some(2));
+        assert_eq!(parse_number("$-10", 10), Some(-16));
+        assert_eq!(parse_number("'a'", 10), Some(97));
+    }
+
+    #[test]
+    fn wraps_to_a_cell() {
+        assert_eq!(parse_number("4294967295", 10), Some(-1));
+        assert_eq!(parse_number("2147483648", 10), Some(i32::MIN));
+        assert_eq!(parse_number("4294967296", 10), None);
+    }
+
+    #[test]
+    fn rejects_other_words() {
+        for tok in &["", "-", "#", "$-", "12a", "2", "dup", "'ab'"] {
+            let base = if *tok == "2" { 2 } else { 10 };
+            assert_eq!(parse_number(tok, base), None, "{}", tok);
+        }
+    }
+
+    #[test]
+    fn formats_in_the_base() {
+        assert_eq!(format_number(255, 16), "FF");
+        assert_eq!(format_number(-5, 2), "-101");
+        assert_eq!(format_number(i32::MIN, 10), "-2147483648");
+        for &n in &[0, 1, -1, 12345, i32::MAX, i32::MIN] {
+            assert_eq!(parse_number(&format_number(n, 7), 7), Some(n));
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use forth::env::{Action, ForthEnv, ForthResult, WordKind};
+use forth::parsing::{parse_name, parse_xt};
+
+// Objects in the style of mini-OOF
+//
+// A class is a created word whose data holds the size of its instances, the
+// size of its method table and then the table itself: the execution token
+// of each method, starting at offset 2. The first cell of an object points
+// to its class, with its variables following.
+
+const INSTANCE_SIZE: i32 = 0;
+const TABLE_SIZE: i32 = 1;
+const FIRST_METHOD: i32 = 2;
+
+// Execution token stored for methods a class does not define
+const NO_METHOD: i32 = -1;
+
+// Address of the cell at the offset into a class or object
+fn cell(base: i32, offset: i32) -> ForthResult<i32> {
+    base.checked_add(offset)
+        .ok_or_else(|| format!("Invalid memory address: {} + {}", base, offset))
+}
+
+// Address of the class's entry for the method at the offset, which has to
+// be within its method table
+fn method_cell(env: &ForthEnv, class: i32, offset: i32) -> ForthResult<i32> {
+    let size = env.fetch(cell(class, TABLE_SIZE)?)?;
+    if offset >= FIRST_METHOD && offset < size {
+        cell(class, offset)
+    } else {
+        Err("Method is not in the class's method table".to_string())
+    }
+}
+
+// Offset in the method table of the method word
+fn method_offset(env: &ForthEnv, xt: usize) -> ForthResult<i32> {
+    let addr = match env.word(xt)?.kind {
+        WordKind::Created(addr, Some(Action::Dispatch)) => addr,
+        _ => return Err(format!("{} is not a method", env.word(xt)?.name)),
+    };
+    env.fetch(addr)
+}
+
+// Action of a method word, which runs the method of the object's class
+pub fn dispatch(env: &mut ForthEnv) -> ForthResult<()> {
+    let addr = env.pop("Empty stack for method".to_string())?;
+    let offset = env.fetch(addr)?;
+    let obj = env.top("Empty stack for object of method".to_string())?;
+    let class = env.fetch(obj)?;
+    match env.fetch(method_cell(env, class, offset)?)? {
+        NO_METHOD => Err("Method is not defined for this class".to_string()),
+        xt => env.execute(xt as usize),
+    }
+}
+
+// Action of an instance variable word, which adds its offset to the object
+pub fn var_field(env: &mut ForthEnv) -> ForthResult<()> {
+    let addr = env.pop("Empty stack for instance variable".to_string())?;
+    let offset = env.fetch(addr)?;
+    let obj = env.pop("Empty stack for object of instance variable".to_string())?;
+    env.push(obj.wrapping_add(offset));
+    Ok(())
+}
+
+// Starts a class inheriting from the given one
+pub fn class(env: &mut ForthEnv) -> ForthResult<()> {
+    let parent = env.pop("Empty stack for class".to_string())?;
+    let methods = env.fetch(cell(parent, TABLE_SIZE)?)?;
+    let vars = env.fetch(cell(parent, INSTANCE_SIZE)?)?;
+    env.push(parent);
+    env.push(methods);
+    env.push(vars);
+    Ok(())
+}
+
+pub fn method(env: &mut ForthEnv) -> ForthResult<()> {
+    let vars = env.pop("Empty stack for method".to_string())?;
+    let methods = env.pop("Empty stack for method".to_string())?;
+    let next = methods
+        .checked_add(1)
+        .ok_or_else(|| "Too many methods".to_string())?;
+    let name = parse_name(env, "method")?;
+    env.add_created(&name);
+    env.comma(methods);
+    env.set_action(Action::Dispatch)?;
+    env.push(next);
+    env.push(vars);
+    Ok(())
+}
+
+pub fn var(env: &mut ForthEnv) -> ForthResult<()> {
+    let size = env.pop("Empty stack for size in var".to_string())?;
+    let vars = env.pop("Empty stack for var".to_string())?;
+    let next = vars
+        .checked_add(size)
+        .ok_or_else(|| format!("Instance variables too large: {} + {}", vars, size))?;
+    let name = parse_name(env, "instance variable")?;
+    env.add_created(&name);
+    env.comma(vars);
+    env.set_action(Action::VarField)?;
+    env.push(next);
+    Ok(())
+}
+
+// Creates the class, inheriting the methods of its parent
+pub fn end_class(env: &mut ForthEnv) -> ForthResult<()> {
+    let vars = env.pop("Empty stack for end-class".to_string())?;
+    let methods = env.pop("Empty stack for end-class".to_string())?;
+    let parent = env.pop("Empty stack for end-class".to_string())?;
+    let inherited = env.fetch(cell(parent, TABLE_SIZE)?)?;
+    let name = parse_name(env, "class")?;
+
+    env.add_created(&name);
+    env.comma(vars);
+    env.comma(methods);
+    for i in FIRST_METHOD..methods {
+        let xt = if i < inherited {
+            env.fetch(cell(parent, i)?)?
+        } else {
+            NO_METHOD
+        };
+        env.comma(xt);
+    }
+    Ok(())
+}
+
+// Makes the execution token the class's implementation of the method
+pub fn defines(env: &mut ForthEnv) -> ForthResult<()> {
+    let class = env.pop("Empty stack for class in defines".to_string())?;
+    let xt = env.pop("Empty stack for execution token in defines".to_string())?;
+    let method = parse_xt(env)?;
+    let offset = method_offset(env, method)?;
+    env.word(xt as usize)?;
+    env.store(method_cell(env, class, offset)?, xt)
+}
+
+pub fn new(env: &mut ForthEnv) -> ForthResult<()> {
+    let class = env.pop("Empty stack for new".to_string())?;
+    let size = env.fetch(cell(class, INSTANCE_SIZE)?)?;
+    if size < 1 {
+        return Err(format!("Invalid instance size: {}", size));
+    }
+    let obj = env.allot(size)?;
+    env.store(obj, class)?;
+    env.push(obj);
+    Ok(())
+}
+
+// Compiles the class's implementation of the method, binding it early
+pub fn early_bind(env: &mut ForthEnv) -> ForthResult<()> {
+    let class = env.pop("Empty stack for class in ::".to_string())?;
+    let method = parse_xt(env)?;
+    let offset = method_offset(env, method)?;
+    match env.fetch(method_cell(env, class, offset)?)? {
+        NO_METHOD => Err("Method is not defined for this class".to_string()),
+        xt => env.compile_xt(xt as usize),
+    }
+}
+
+// Registers the object words along with the root class
+pub fn init(env: &mut ForthEnv) {
+    env.add_created("object");
+    env.comma(1);
+    env.comma(FIRST_METHOD);
+
+    env.add_builtin("class", class);
+    env.add_builtin("method", method);
+    env.add_builtin("var", var);
+    env.add_builtin("end-class", end_class);
+    env.add_builtin("defines", defines);
+    env.add_builtin("new", new);
+    env.add_builtin("::", early_bind);
+}
+
+#[cfg(test)]
+mod tests {
+    use forth::env::ForthEnv;
+    use forth::inter::tests::{eval, run};
+
+    const POINT: &str = "object class
+1 var x
+method show
+end-class point
+: show-point drop 42 ;
+' show-point point defines show";
+
+    fn with_point(src: &str) -> Result<Vec<i32>, String> {
+        run(&format!("{}\n{}", POINT, src))
+    }
+
+    #[test]
+    fn methods_dispatch_on_class() {
+        assert_eq!(with_point("point new show"), Ok(vec![42]));
+        assert_eq!(with_point("point new dup 7 swap x ! x @"), Ok(vec![7]));
+    }
+
+    #[test]
+    fn methods_stay_in_class_table() {
+        let outside = Err("Method is not in the class's method table".to_string());
+        assert_eq!(with_point("5 6 object new show"), outside);
+        assert_eq!(with_point("' + object defines show"), outside);
+    }
+
+    #[test]
+    fn only_methods_can_be_defined() {
+        let res = with_point("' show-point point defines x");
+        assert_eq!(res, Err("x is not a method".to_string()));
+        let res = with_point("create m 2 , ' show-point point defines m");
+        assert_eq!(res, Err("m is not a method".to_string()));
+
+        let mut env = ForthEnv::empty();
+        eval(&mut env, POINT).unwrap();
+        let see = |name| env.decompile(env.find(name).unwrap()).unwrap();
+        assert_eq!(see("show"), "\\ show is a method at offset 2");
+        assert_eq!(see("x"), "\\ x is an instance variable at offset 1");
+    }
+
+    #[test]
+    fn class_addresses_do_not_overflow() {
+        assert!(run("2147483647 class").is_err());
+        assert!(with_point("create o 2147483647 , o show").is_err());
+        assert!(run("2147483647 5 method foo").is_err());
+        assert!(run("0 2147483647 1 var foo").is_err());
+        assert_eq!(run("2 5 method foo"), Ok(vec![3, 5]));
+    }
+
+    #[test]
+    fn instances_need_a_size() {
+        let mut env = ForthEnv::empty();
+        eval(&mut env, "create buf 10 allot create c -3 , 2 ,").unwrap();
+        let here = env.here();
+        let res = eval(&mut env, "c new");
+        assert_eq!(res, Err("Invalid instance size: -3".to_string()));
+        assert_eq!(env.here(), here);
+        eval(&mut env, "c @").unwrap();
+        assert_eq!(env.pop(String::new()), Ok(-3));
+    }
+}
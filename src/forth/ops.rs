@@ -1,17 +1,15 @@
-use forth::env::{ForthEnv, ForthResult};
+use std::cmp::Ordering;
+
+use forth::compiler::{Compiler, Instr};
+use forth::env::{ForthEnv, ForthResult, FORTH_WORDLIST, ROOT_WORDLIST};
+use forth::number;
 
 // Binary operations
 type BinOp = fn(i32, i32) -> i32;
 
 fn binary_op(name: &str, op: BinOp, env: &mut ForthEnv) -> ForthResult<()> {
-    let x = env.pop(format!(
-        "Empty stack: for first argument for {}",
-        name.to_string()
-    ))?;
-    let y = env.pop(format!(
-        "Empty stack: for second argument for {}",
-        name.to_string()
-    ))?;
+    let x = env.pop(format!("Empty stack: for first argument for {}", name))?;
+    let y = env.pop(format!("Empty stack: for second argument for {}", name))?;
     env.push(op(x, y));
     Ok(())
 }
@@ -54,7 +52,7 @@ pub fn dup(env: &mut ForthEnv) -> ForthResult<()> {
 
 pub fn pop(env: &mut ForthEnv) -> ForthResult<()> {
     let x = env.pop("Empty stack for .".to_string())?;
-    println!("{}", x);
+    println!("{}", number::format_number(x, env.base()?));
     Ok(())
 }
 
@@ -90,9 +88,95 @@ pub fn drop(env: &mut ForthEnv) -> ForthResult<()> {
     Ok(())
 }
 
+pub fn nip(env: &mut ForthEnv) -> ForthResult<()> {
+    let x = env.pop("Empty stack for first element in nip".to_string())?;
+    env.pop("Empty stack for second element in nip".to_string())?;
+    env.push(x);
+    Ok(())
+}
+
+pub fn tuck(env: &mut ForthEnv) -> ForthResult<()> {
+    let x = env.pop("Empty stack for first element in tuck".to_string())?;
+    let y = env.pop("Empty stack for second element in tuck".to_string())?;
+    env.push(x);
+    env.push(y);
+    env.push(x);
+    Ok(())
+}
+
+pub fn minus_rot(env: &mut ForthEnv) -> ForthResult<()> {
+    let x = env.pop("Empty stack for first element in -rot".to_string())?;
+    let y = env.pop("Empty stack for second element in -rot".to_string())?;
+    let z = env.pop("Empty stack for third element in -rot".to_string())?;
+    env.push(x);
+    env.push(z);
+    env.push(y);
+    Ok(())
+}
+
+pub fn question_dup(env: &mut ForthEnv) -> ForthResult<()> {
+    let x = env.top("Empty stack for ?dup".to_string())?;
+    if x != 0 {
+        env.push(x);
+    }
+    Ok(())
+}
+
+pub fn pick(env: &mut ForthEnv) -> ForthResult<()> {
+    let n = env.pop("Empty stack for pick".to_string())?;
+    let x = env.pick(n)?;
+    env.push(x);
+    Ok(())
+}
+
+pub fn roll(env: &mut ForthEnv) -> ForthResult<()> {
+    let n = env.pop("Empty stack for roll".to_string())?;
+    env.roll(n)
+}
+
+pub fn depth(env: &mut ForthEnv) -> ForthResult<()> {
+    let n = env.depth() as i32;
+    env.push(n);
+    Ok(())
+}
+
+pub fn clear(env: &mut ForthEnv) -> ForthResult<()> {
+    env.clear();
+    Ok(())
+}
+
+pub fn print_depth_stack(env: &mut ForthEnv) -> ForthResult<()> {
+    env.print_depth_stack();
+    Ok(())
+}
+
+fn to_char(x: i32) -> ForthResult<char> {
+    match std::char::from_u32(x as u32) {
+        Some(c) => Ok(c),
+        None => Err(format!("Invalid character code: {}", x)),
+    }
+}
+
+fn encode_char(c: char) -> Vec<u8> {
+    let mut buf = [0; 4];
+    c.encode_utf8(&mut buf).as_bytes().to_vec()
+}
+
+pub fn loop_index(env: &mut ForthEnv) -> ForthResult<()> {
+    let i = env.loop_index(0)?;
+    env.push(i);
+    Ok(())
+}
+
+pub fn outer_loop_index(env: &mut ForthEnv) -> ForthResult<()> {
+    let j = env.loop_index(1)?;
+    env.push(j);
+    Ok(())
+}
+
 pub fn emit(env: &mut ForthEnv) -> ForthResult<()> {
     let x = env.pop("Empty stack for emit".to_string())?;
-    print!("{}", (x as u8) as char);
+    print!("{}", to_char(x)?);
     Ok(())
 }
 
@@ -101,21 +185,712 @@ pub fn cr(_: &mut ForthEnv) -> ForthResult<()> {
     Ok(())
 }
 
-pub fn print_stack(env: &mut ForthEnv) -> ForthResult<()> {
-    print!("Stack: ");
-    env.print_stack();
+pub fn type_string(env: &mut ForthEnv) -> ForthResult<()> {
+    let len = env.pop("Empty stack for length in type".to_string())?;
+    let addr = env.pop("Empty stack for address in type".to_string())?;
+    let bytes = env.read_bytes(addr, len)?;
+    print!("{}", String::from_utf8_lossy(&bytes));
+    Ok(())
+}
+
+pub fn abs(env: &mut ForthEnv) -> ForthResult<()> {
+    let x = env.pop("Empty stack for abs".to_string())?;
+    env.push(x.wrapping_abs());
+    Ok(())
+}
+
+pub fn words(env: &mut ForthEnv) -> ForthResult<()> {
+    env.print_words();
+    Ok(())
+}
+
+// Memory operations
+pub fn fetch(env: &mut ForthEnv) -> ForthResult<()> {
+    let addr = env.pop("Empty stack for address in @".to_string())?;
+    let x = env.fetch(addr)?;
+    env.push(x);
+    Ok(())
+}
+
+pub fn store(env: &mut ForthEnv) -> ForthResult<()> {
+    let addr = env.pop("Empty stack for address in !".to_string())?;
+    let x = env.pop("Empty stack for value in !".to_string())?;
+    env.store(addr, x)
+}
+
+pub fn char_fetch(env: &mut ForthEnv) -> ForthResult<()> {
+    let addr = env.pop("Empty stack for address in c@".to_string())?;
+    let c = env.fetch(addr)? & 0xff;
+    env.push(c);
+    Ok(())
+}
+
+pub fn char_store(env: &mut ForthEnv) -> ForthResult<()> {
+    let addr = env.pop("Empty stack for address in c!".to_string())?;
+    let c = env.pop("Empty stack for value in c!".to_string())?;
+    env.store(addr, c & 0xff)
+}
+
+pub fn comma(env: &mut ForthEnv) -> ForthResult<()> {
+    let x = env.pop("Empty stack for ,".to_string())?;
+    env.comma(x);
+    Ok(())
+}
+
+pub fn char_comma(env: &mut ForthEnv) -> ForthResult<()> {
+    let c = env.pop("Empty stack for c,".to_string())?;
+    env.comma(c & 0xff);
+    Ok(())
+}
+
+// Copies cells, which may overlap, from one address to another
+pub fn move_cells(env: &mut ForthEnv) -> ForthResult<()> {
+    let n = env.pop("Empty stack for count in move".to_string())?;
+    let dest = env.pop("Empty stack for destination in move".to_string())?;
+    let src = env.pop("Empty stack for source in move".to_string())?;
+    let cells = (0..n.max(0))
+        .map(|i| env.fetch(src + i))
+        .collect::<ForthResult<Vec<_>>>()?;
+    for (i, &x) in cells.iter().enumerate() {
+        env.store(dest + i as i32, x)?;
+    }
+    Ok(())
+}
+
+pub fn here(env: &mut ForthEnv) -> ForthResult<()> {
+    let addr = env.here();
+    env.push(addr);
+    Ok(())
+}
+
+pub fn allot(env: &mut ForthEnv) -> ForthResult<()> {
+    let n = env.pop("Empty stack for allot".to_string())?;
+    env.allot(n)?;
+    Ok(())
+}
+
+// Cells and characters both take up a single address unit, so `cells` and
+// `chars` only check that there is something to convert
+pub fn cells(env: &mut ForthEnv) -> ForthResult<()> {
+    env.top("Empty stack to evaluate cells".to_string())?;
+    Ok(())
+}
+
+pub fn chars(env: &mut ForthEnv) -> ForthResult<()> {
+    env.top("Empty stack to evaluate chars".to_string())?;
+    Ok(())
+}
+
+// String operations
+pub fn count(env: &mut ForthEnv) -> ForthResult<()> {
+    let addr = env.pop("Empty stack for count".to_string())?;
+    let len = env.fetch(addr)?;
+    env.push(addr + 1);
+    env.push(len);
+    Ok(())
+}
+
+fn pop_string(env: &mut ForthEnv, name: &str) -> ForthResult<Vec<u8>> {
+    let len = env.pop(format!("Empty stack for string length in {}", name))?;
+    let addr = env.pop(format!("Empty stack for string address in {}", name))?;
+    env.read_bytes(addr, len)
+}
+
+pub fn compare(env: &mut ForthEnv) -> ForthResult<()> {
+    let s2 = pop_string(env, "compare")?;
+    let s1 = pop_string(env, "compare")?;
+    env.push(match s1.cmp(&s2) {
+        Ordering::Less => -1,
+        Ordering::Equal => 0,
+        Ordering::Greater => 1,
+    });
+    Ok(())
+}
+
+pub fn search(env: &mut ForthEnv) -> ForthResult<()> {
+    let needle = pop_string(env, "search")?;
+    let len = env.pop("Empty stack for string length in search".to_string())?;
+    let addr = env.pop("Empty stack for string address in search".to_string())?;
+    let haystack = env.read_bytes(addr, len)?;
+
+    let found = if needle.is_empty() {
+        Some(0)
+    } else {
+        haystack
+            .windows(needle.len())
+            .position(|w| w == needle.as_slice())
+    };
+
+    match found {
+        Some(pos) => {
+            env.push(addr + pos as i32);
+            env.push(len - pos as i32);
+            env.push(-1);
+        }
+        None => {
+            env.push(addr);
+            env.push(len);
+            env.push(0);
+        }
+    }
+    Ok(())
+}
+
+pub fn slash_string(env: &mut ForthEnv) -> ForthResult<()> {
+    let n = env.pop("Empty stack for count in /string".to_string())?;
+    let len = env.pop("Empty stack for string length in /string".to_string())?;
+    let addr = env.pop("Empty stack for string address in /string".to_string())?;
+    match (addr.checked_add(n), len.checked_sub(n)) {
+        (Some(addr), Some(len)) => {
+            env.push(addr);
+            env.push(len);
+            Ok(())
+        }
+        _ => Err(format!("Invalid memory address: {} + {}", addr, n)),
+    }
+}
+
+// Extended characters
+//
+// Strings in memory are UTF-8 encoded with one byte per address unit, these
+// words step through them a whole character at a time.
+
+// Size in bytes of a UTF-8 sequence given its first byte
+fn utf8_len(first: u8) -> usize {
+    match first {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 0,
+    }
+}
+
+// Decodes the character at addr, reading at most max bytes, and returns it
+// along with its size
+fn decode_xchar(env: &ForthEnv, addr: i32, max: i32) -> ForthResult<(char, i32)> {
+    let first = env.fetch(addr)? as u8;
+    let len = utf8_len(first) as i32;
+    if len == 0 || len > max {
+        return Err(format!("Invalid UTF-8 sequence at address: {}", addr));
+    }
+    let bytes = env.read_bytes(addr, len)?;
+    match std::str::from_utf8(&bytes) {
+        Ok(s) => Ok((s.chars().next().unwrap(), len)),
+        Err(_) => Err(format!("Invalid UTF-8 sequence at address: {}", addr)),
+    }
+}
+
+pub fn xc_size(env: &mut ForthEnv) -> ForthResult<()> {
+    let x = env.pop("Empty stack for xc-size".to_string())?;
+    env.push(to_char(x)?.len_utf8() as i32);
+    Ok(())
+}
+
+pub fn xc_fetch_plus(env: &mut ForthEnv) -> ForthResult<()> {
+    let addr = env.pop("Empty stack for xc@+".to_string())?;
+    let (c, len) = decode_xchar(env, addr, 4)?;
+    env.push(addr + len);
+    env.push(c as i32);
+    Ok(())
+}
+
+pub fn xc_store_plus(env: &mut ForthEnv) -> ForthResult<()> {
+    let addr = env.pop("Empty stack for address in xc!+".to_string())?;
+    let x = env.pop("Empty stack for character in xc!+".to_string())?;
+    let bytes = encode_char(to_char(x)?);
+    for (i, &b) in bytes.iter().enumerate() {
+        env.store(addr + i as i32, i32::from(b))?;
+    }
+    env.push(addr + bytes.len() as i32);
+    Ok(())
+}
+
+pub fn xchar_plus(env: &mut ForthEnv) -> ForthResult<()> {
+    let addr = env.pop("Empty stack for xchar+".to_string())?;
+    let (_, len) = decode_xchar(env, addr, 4)?;
+    env.push(addr + len);
+    Ok(())
+}
+
+pub fn xchar_minus(env: &mut ForthEnv) -> ForthResult<()> {
+    let mut addr = env.pop("Empty stack for xchar-".to_string())?;
+    // Step back over continuation bytes to the start of the character
+    loop {
+        addr = match addr.checked_sub(1) {
+            Some(prev) => prev,
+            None => return Err("Invalid memory address for xchar-".to_string()),
+        };
+        if env.fetch(addr)? & 0xc0 != 0x80 {
+            break;
+        }
+    }
+    env.push(addr);
+    Ok(())
+}
+
+pub fn x_size(env: &mut ForthEnv) -> ForthResult<()> {
+    let len = env.pop("Empty stack for string length in x-size".to_string())?;
+    let addr = env.pop("Empty stack for string address in x-size".to_string())?;
+    let (_, size) = decode_xchar(env, addr, len)?;
+    env.push(size);
+    Ok(())
+}
+
+pub fn plus_x_string(env: &mut ForthEnv) -> ForthResult<()> {
+    let len = env.pop("Empty stack for string length in +x/string".to_string())?;
+    let addr = env.pop("Empty stack for string address in +x/string".to_string())?;
+    let (_, size) = decode_xchar(env, addr, len)?;
+    env.push(addr + size);
+    env.push(len - size);
+    Ok(())
+}
+
+pub fn x_string_minus(env: &mut ForthEnv) -> ForthResult<()> {
+    let len = env.pop("Empty stack for string length in x\\string-".to_string())?;
+    let addr = env.pop("Empty stack for string address in x\\string-".to_string())?;
+    let bytes = env.read_bytes(addr, len)?;
+    let last = match std::str::from_utf8(&bytes) {
+        Ok(s) => s.chars().last().map_or(0, |c| c.len_utf8() as i32),
+        Err(_) => return Err(format!("Invalid UTF-8 string at address: {}", addr)),
+    };
+    env.push(addr);
+    env.push(len - last);
+    Ok(())
+}
+
+pub fn x_width(env: &mut ForthEnv) -> ForthResult<()> {
+    let bytes = pop_string(env, "x-width")?;
+    env.push(String::from_utf8_lossy(&bytes).chars().count() as i32);
+    Ok(())
+}
+
+// Dynamic strings
+//
+// Growable strings live on a heap in the environment and are referred to by
+// handles on the data stack.
+pub fn str_new(env: &mut ForthEnv) -> ForthResult<()> {
+    let h = env.str_new(vec![]);
+    env.push(h);
+    Ok(())
+}
+
+pub fn str_free(env: &mut ForthEnv) -> ForthResult<()> {
+    let h = env.pop("Empty stack for str-free".to_string())?;
+    env.str_free(h)
+}
+
+pub fn str_append(env: &mut ForthEnv) -> ForthResult<()> {
+    let bytes = pop_string(env, "str-append")?;
+    let h = env.pop("Empty stack for handle in str-append".to_string())?;
+    env.str_get_mut(h)?.extend(bytes);
+    Ok(())
+}
+
+pub fn str_append_char(env: &mut ForthEnv) -> ForthResult<()> {
+    let c = env.pop("Empty stack for char in str-append-char".to_string())?;
+    let h = env.pop("Empty stack for handle in str-append-char".to_string())?;
+    let bytes = encode_char(to_char(c)?);
+    env.str_get_mut(h)?.extend(bytes);
+    Ok(())
+}
+
+pub fn str_append_str(env: &mut ForthEnv) -> ForthResult<()> {
+    let h2 = env.pop("Empty stack for second handle in str-append-str".to_string())?;
+    let h1 = env.pop("Empty stack for first handle in str-append-str".to_string())?;
+    let bytes = env.str_get(h2)?.clone();
+    env.str_get_mut(h1)?.extend(bytes);
+    Ok(())
+}
+
+pub fn str_len(env: &mut ForthEnv) -> ForthResult<()> {
+    let h = env.pop("Empty stack for str-len".to_string())?;
+    let len = env.str_get(h)?.len() as i32;
+    env.push(len);
+    Ok(())
+}
+
+pub fn str_clear(env: &mut ForthEnv) -> ForthResult<()> {
+    let h = env.pop("Empty stack for str-clear".to_string())?;
+    env.str_get_mut(h)?.clear();
+    Ok(())
+}
+
+pub fn str_type(env: &mut ForthEnv) -> ForthResult<()> {
+    let h = env.pop("Empty stack for str-type".to_string())?;
+    print!("{}", String::from_utf8_lossy(env.str_get(h)?));
+    Ok(())
+}
+
+pub fn str_to_number(env: &mut ForthEnv) -> ForthResult<()> {
+    let h = env.pop("Empty stack for str>number".to_string())?;
+    let base = env.base()?;
+    let parsed = String::from_utf8_lossy(env.str_get(h)?).trim().to_string();
+    match number::parse_number(&parsed, base) {
+        Some(n) => {
+            env.push(n);
+            env.push(-1);
+        }
+        None => env.push(0),
+    }
+    Ok(())
+}
+
+pub fn number_to_str(env: &mut ForthEnv) -> ForthResult<()> {
+    let n = env.pop("Empty stack for number>str".to_string())?;
+    let text = number::format_number(n, env.base()?);
+    let h = env.str_new(text.into_bytes());
+    env.push(h);
+    Ok(())
+}
+
+// Pictured numeric output
+//
+// Works on unsigned double cells, with the high cell on top of the low one,
+// so `123 0 <# #s #> type` prints 123. The digits are collected right to
+// left until `#>` leaves `addr len`.
+fn pop_double(env: &mut ForthEnv, word: &str) -> ForthResult<u64> {
+    let high = env.pop(format!("Empty stack for {}", word))? as u32;
+    let low = env.pop(format!("Empty stack for {}", word))? as u32;
+    Ok(u64::from(high) << 32 | u64::from(low))
+}
+
+fn push_double(env: &mut ForthEnv, x: u64) {
+    env.push(x as u32 as i32);
+    env.push((x >> 32) as u32 as i32);
+}
+
+pub fn pictured_start(env: &mut ForthEnv) -> ForthResult<()> {
+    env.hold_start();
+    Ok(())
+}
+
+fn hold_digit(env: &mut ForthEnv, x: u64) -> ForthResult<u64> {
+    let base = u64::from(env.base()?);
+    env.hold(number::digit_char((x % base) as u32) as u8)?;
+    Ok(x / base)
+}
+
+pub fn pictured_digit(env: &mut ForthEnv) -> ForthResult<()> {
+    let x = pop_double(env, "#")?;
+    let rest = hold_digit(env, x)?;
+    push_double(env, rest);
+    Ok(())
+}
+
+pub fn pictured_digits(env: &mut ForthEnv) -> ForthResult<()> {
+    let mut x = pop_double(env, "#s")?;
+    loop {
+        x = hold_digit(env, x)?;
+        if x == 0 {
+            push_double(env, 0);
+            return Ok(());
+        }
+    }
+}
+
+pub fn pictured_hold(env: &mut ForthEnv) -> ForthResult<()> {
+    let c = env.pop("Empty stack for hold".to_string())?;
+    env.hold(c as u8)
+}
+
+pub fn pictured_xhold(env: &mut ForthEnv) -> ForthResult<()> {
+    let x = env.pop("Empty stack for xhold".to_string())?;
+    for b in encode_char(to_char(x)?).into_iter().rev() {
+        env.hold(b)?;
+    }
+    Ok(())
+}
+
+pub fn pictured_sign(env: &mut ForthEnv) -> ForthResult<()> {
+    let n = env.pop("Empty stack for sign".to_string())?;
+    if n < 0 {
+        env.hold(b'-')?;
+    }
+    Ok(())
+}
+
+pub fn pictured_end(env: &mut ForthEnv) -> ForthResult<()> {
+    pop_double(env, "#>")?;
+    let len = env.hold_end();
+    env.push(0);
+    env.push(len);
+    Ok(())
+}
+
+fn print_right(s: &str, width: i32) {
+    print!("{:>width$}", s, width = width.max(0) as usize);
+}
+
+pub fn pop_unsigned(env: &mut ForthEnv) -> ForthResult<()> {
+    let x = env.pop("Empty stack for u.".to_string())?;
+    println!("{}", number::format_unsigned(x as u32, env.base()?));
+    Ok(())
+}
+
+pub fn pop_right(env: &mut ForthEnv) -> ForthResult<()> {
+    let width = env.pop("Empty stack for width in .r".to_string())?;
+    let x = env.pop("Empty stack for number in .r".to_string())?;
+    print_right(&number::format_number(x, env.base()?), width);
+    Ok(())
+}
+
+pub fn pop_unsigned_right(env: &mut ForthEnv) -> ForthResult<()> {
+    let width = env.pop("Empty stack for width in u.r".to_string())?;
+    let x = env.pop("Empty stack for number in u.r".to_string())?;
+    print_right(&number::format_unsigned(x as u32, env.base()?), width);
+    Ok(())
+}
+
+// Number base
+pub fn hex(env: &mut ForthEnv) -> ForthResult<()> {
+    env.set_base(16);
+    Ok(())
+}
+
+pub fn decimal(env: &mut ForthEnv) -> ForthResult<()> {
+    env.set_base(10);
+    Ok(())
+}
+
+pub fn binary(env: &mut ForthEnv) -> ForthResult<()> {
+    env.set_base(2);
     Ok(())
 }
 
-pub fn print_func(env: &mut ForthEnv) -> ForthResult<()> {
-    print!("Dictionary: ");
-    env.print_func();
+// Compiling words, these act on the definition being compiled
+
+// Control structures outside of a definition get compiled on the fly, and
+// run once they have been closed
+fn control_compiler(env: &mut ForthEnv) -> ForthResult<&mut Compiler> {
+    if env.compiler().is_err() {
+        env.begin_compile(Compiler::new());
+    }
+    env.compiler()
+}
+
+pub fn compile_if(env: &mut ForthEnv) -> ForthResult<()> {
+    control_compiler(env)?.begin_if();
+    Ok(())
+}
+
+pub fn compile_else(env: &mut ForthEnv) -> ForthResult<()> {
+    env.compiler()?.begin_else()
+}
+
+pub fn compile_then(env: &mut ForthEnv) -> ForthResult<()> {
+    env.compiler()?.end_if()
+}
+
+pub fn compile_do(env: &mut ForthEnv) -> ForthResult<()> {
+    control_compiler(env)?.begin_do();
+    Ok(())
+}
+
+pub fn compile_loop(env: &mut ForthEnv) -> ForthResult<()> {
+    env.compiler()?.end_do()
+}
+
+pub fn recurse(env: &mut ForthEnv) -> ForthResult<()> {
+    env.compiler()?.recurse()
+}
+
+pub fn does(env: &mut ForthEnv) -> ForthResult<()> {
+    env.compiler()?.does()
+}
+
+pub fn literal(env: &mut ForthEnv) -> ForthResult<()> {
+    env.compiler()?;
+    let x = env.pop("Empty stack for literal".to_string())?;
+    env.compiler()?.emit(Instr::Literal(x));
+    Ok(())
+}
+
+pub fn end_definition(env: &mut ForthEnv) -> ForthResult<()> {
+    let (name, xt) = match env.compiler()?.function() {
+        Some((name, xt)) => (name.to_string(), xt),
+        None => return Err("; without matching :".to_string()),
+    };
+    let comp = env.end_compile()?;
+    env.define_function(xt, comp)?;
+    println!("Defined: {}", name);
+    Ok(())
+}
+
+pub fn left_bracket(env: &mut ForthEnv) -> ForthResult<()> {
+    env.compiler()?;
+    env.set_compiling(false);
     Ok(())
 }
 
-pub fn print_vars(env: &mut ForthEnv) -> ForthResult<()> {
-    print!("Variables: ");
-    env.print_vars();
+pub fn right_bracket(env: &mut ForthEnv) -> ForthResult<()> {
+    env.compiler()?;
+    env.set_compiling(true);
+    Ok(())
+}
+
+pub fn immediate(env: &mut ForthEnv) -> ForthResult<()> {
+    env.set_immediate()
+}
+
+// Execution tokens
+pub fn execute(env: &mut ForthEnv) -> ForthResult<()> {
+    let xt = env.pop("Empty stack for execute".to_string())?;
+    env.execute(xt as usize)
+}
+
+pub fn to_body(env: &mut ForthEnv) -> ForthResult<()> {
+    let xt = env.pop("Empty stack for >body".to_string())?;
+    let addr = env.body(xt as usize)?;
+    env.push(addr);
+    Ok(())
+}
+
+pub fn compile_comma(env: &mut ForthEnv) -> ForthResult<()> {
+    env.compiler()?;
+    let xt = env.pop("Empty stack for compile,".to_string())?;
+    env.compile_xt(xt as usize)
+}
+
+pub fn defer_store(env: &mut ForthEnv) -> ForthResult<()> {
+    let xt = env.pop("Empty stack for deferred word in defer!".to_string())?;
+    let action = env.pop("Empty stack for action in defer!".to_string())?;
+    env.defer_set(xt as usize, action as usize)
+}
+
+pub fn defer_fetch(env: &mut ForthEnv) -> ForthResult<()> {
+    let xt = env.pop("Empty stack for defer@".to_string())?;
+    match env.defer_get(xt as usize)? {
+        Some(action) => {
+            env.push(action as i32);
+            Ok(())
+        }
+        None => Err("Deferred word has no action".to_string()),
+    }
+}
+
+// Structures
+pub fn end_structure(env: &mut ForthEnv) -> ForthResult<()> {
+    let size = env.pop("Empty stack for size in end-structure".to_string())?;
+    let xt = env.pop("Empty stack for structure in end-structure".to_string())?;
+    env.set_constant(xt as usize, size)
+}
+
+// Values
+// Search order
+pub fn wordlist(env: &mut ForthEnv) -> ForthResult<()> {
+    let wid = env.add_wordlist(None);
+    env.push(wid as i32);
+    Ok(())
+}
+
+pub fn forth_wordlist(env: &mut ForthEnv) -> ForthResult<()> {
+    env.push(FORTH_WORDLIST as i32);
+    Ok(())
+}
+
+pub fn search_wordlist(env: &mut ForthEnv) -> ForthResult<()> {
+    let wid = env.pop("Empty stack for wordlist in search-wordlist".to_string())?;
+    let name = pop_string(env, "search-wordlist")?;
+    let name = String::from_utf8_lossy(&name);
+    match env.search_wordlist(wid as usize, &name)? {
+        Some(xt) => {
+            env.push(xt as i32);
+            env.push(if env.is_immediate(xt) { 1 } else { -1 });
+        }
+        None => env.push(0),
+    }
+    Ok(())
+}
+
+pub fn get_order(env: &mut ForthEnv) -> ForthResult<()> {
+    let order = env.get_order().to_vec();
+    for &wid in order.iter().rev() {
+        env.push(wid as i32);
+    }
+    env.push(order.len() as i32);
+    Ok(())
+}
+
+// Minimum search order, which `forth` turns into the default one
+fn minimum_order() -> Vec<usize> {
+    vec![ROOT_WORDLIST, ROOT_WORDLIST]
+}
+
+// Takes `widn ... wid1 n`, where wid1 is searched first. A count of -1
+// sets the minimum search order.
+pub fn set_order(env: &mut ForthEnv) -> ForthResult<()> {
+    let n = env.pop("Empty stack for set-order".to_string())?;
+    if n == -1 {
+        return env.set_order(minimum_order());
+    }
+    if n < 0 || n as usize > env.depth() {
+        return Err(format!(
+            "Cannot set search order of {} wordlists when stack depth is: {}",
+            n,
+            env.depth()
+        ));
+    }
+
+    let mut order = vec![];
+    for _ in 0..n {
+        order.push(env.pop("Not enough wordlists on stack for set-order".to_string())? as usize);
+    }
+    env.set_order(order)
+}
+
+pub fn get_current(env: &mut ForthEnv) -> ForthResult<()> {
+    let wid = env.get_current();
+    env.push(wid as i32);
+    Ok(())
+}
+
+pub fn set_current(env: &mut ForthEnv) -> ForthResult<()> {
+    let wid = env.pop("Empty stack for set-current".to_string())?;
+    env.set_current(wid as usize)
+}
+
+// Makes new definitions go into the first wordlist in the search order
+pub fn definitions(env: &mut ForthEnv) -> ForthResult<()> {
+    match env.get_order().first() {
+        Some(&wid) => env.set_current(wid),
+        None => Err("Search order is empty".to_string()),
+    }
+}
+
+pub fn forth(env: &mut ForthEnv) -> ForthResult<()> {
+    let mut order = env.get_order().to_vec();
+    match order.first_mut() {
+        Some(first) => *first = FORTH_WORDLIST,
+        None => order.push(FORTH_WORDLIST),
+    }
+    env.set_order(order)
+}
+
+pub fn only(env: &mut ForthEnv) -> ForthResult<()> {
+    env.set_order(minimum_order())
+}
+
+pub fn also(env: &mut ForthEnv) -> ForthResult<()> {
+    let mut order = env.get_order().to_vec();
+    match order.first() {
+        Some(&wid) => order.insert(0, wid),
+        None => return Err("Search order is empty".to_string()),
+    }
+    env.set_order(order)
+}
+
+pub fn previous(env: &mut ForthEnv) -> ForthResult<()> {
+    let mut order = env.get_order().to_vec();
+    if order.len() < 2 {
+        return Err("Cannot empty the search order".to_string());
+    }
+    order.remove(0);
+    env.set_order(order)
+}
+
+pub fn order(env: &mut ForthEnv) -> ForthResult<()> {
+    env.print_order();
     Ok(())
 }
 
@@ -123,14 +898,8 @@ pub fn print_vars(env: &mut ForthEnv) -> ForthResult<()> {
 type BinBoolOp = fn(i32, i32) -> bool;
 
 fn binary_bool_op(name: &str, op: BinBoolOp, env: &mut ForthEnv) -> ForthResult<()> {
-    let x = env.pop(format!(
-        "Empty stack: for first argument for {}",
-        name.to_string()
-    ))?;
-    let y = env.pop(format!(
-        "Empty stack: for second argument for {}",
-        name.to_string()
-    ))?;
+    let x = env.pop(format!("Empty stack: for first argument for {}", name))?;
+    let y = env.pop(format!("Empty stack: for second argument for {}", name))?;
     if op(x, y) {
         env.push(-1);
     } else {
@@ -168,3 +937,341 @@ pub fn invert(env: &mut ForthEnv) -> ForthResult<()> {
     env.push(if x == 0 { -1 } else { 0 });
     Ok(())
 }
+
+// Registers the builtin words
+pub fn init(env: &mut ForthEnv) {
+    // Binary ops
+    env.add_builtin("+", add);
+    env.add_builtin("-", subtract);
+    env.add_builtin("*", mul);
+    env.add_builtin("/", div);
+    env.add_builtin("mod", modulus);
+    env.add_builtin("and", and);
+    env.add_builtin("or", or);
+
+    // Core ops
+    env.add_builtin("words", words);
+    env.add_builtin("dup", dup);
+    env.add_builtin(".", pop);
+    env.add_builtin("drop", drop);
+    env.add_builtin("swap", swap);
+    env.add_builtin("over", over);
+    env.add_builtin("rot", rot);
+    env.add_builtin("-rot", minus_rot);
+    env.add_builtin("nip", nip);
+    env.add_builtin("tuck", tuck);
+    env.add_builtin("?dup", question_dup);
+    env.add_builtin("pick", pick);
+    env.add_builtin("roll", roll);
+    env.add_builtin("depth", depth);
+    env.add_builtin("clear", clear);
+    env.add_builtin(".s", print_depth_stack);
+    env.add_builtin("emit", emit);
+    env.add_builtin("cr", cr);
+    env.add_builtin("i", loop_index);
+    env.add_builtin("j", outer_loop_index);
+    env.add_builtin("abs", abs);
+
+    // Boolean ops
+    env.add_builtin("=", eq);
+    env.add_builtin("!=", not_eq);
+    env.add_builtin("<", lt);
+    env.add_builtin(">", gt);
+    env.add_builtin("<=", lt_eq);
+    env.add_builtin(">=", gt_eq);
+    env.add_builtin("invert", invert);
+
+    // Memory ops
+    env.add_builtin("@", fetch);
+    env.add_builtin("!", store);
+    env.add_builtin("c@", char_fetch);
+    env.add_builtin("c!", char_store);
+    env.add_builtin(",", comma);
+    env.add_builtin("c,", char_comma);
+    env.add_builtin("move", move_cells);
+    env.add_builtin("here", here);
+    env.add_builtin("allot", allot);
+    env.add_builtin("cells", cells);
+    env.add_builtin("chars", chars);
+
+    // String ops
+    env.add_builtin("type", type_string);
+    env.add_builtin("count", count);
+    env.add_builtin("compare", compare);
+    env.add_builtin("search", search);
+    env.add_builtin("/string", slash_string);
+
+    // Extended characters
+    env.add_builtin("xemit", emit);
+    env.add_builtin("xc-size", xc_size);
+    env.add_builtin("xc@+", xc_fetch_plus);
+    env.add_builtin("xc!+", xc_store_plus);
+    env.add_builtin("xchar+", xchar_plus);
+    env.add_builtin("xchar-", xchar_minus);
+    env.add_builtin("x-size", x_size);
+    env.add_builtin("+x/string", plus_x_string);
+    env.add_builtin("x\\string-", x_string_minus);
+    env.add_builtin("x-width", x_width);
+
+    // Dynamic strings
+    env.add_builtin("str-new", str_new);
+    env.add_builtin("str-free", str_free);
+    env.add_builtin("str-append", str_append);
+    env.add_builtin("str-append-char", str_append_char);
+    env.add_builtin("str-append-str", str_append_str);
+    env.add_builtin("str-len", str_len);
+    env.add_builtin("str-clear", str_clear);
+    env.add_builtin("str-type", str_type);
+    env.add_builtin("str>number", str_to_number);
+    env.add_builtin("number>str", number_to_str);
+
+    // Pictured numeric output
+    env.add_builtin("<#", pictured_start);
+    env.add_builtin("#", pictured_digit);
+    env.add_builtin("#s", pictured_digits);
+    env.add_builtin("hold", pictured_hold);
+    env.add_builtin("xhold", pictured_xhold);
+    env.add_builtin("sign", pictured_sign);
+    env.add_builtin("#>", pictured_end);
+    env.add_builtin("u.", pop_unsigned);
+    env.add_builtin(".r", pop_right);
+    env.add_builtin("u.r", pop_unsigned_right);
+
+    // Compiling words
+    env.add_builtin("]", right_bracket);
+    env.add_builtin("immediate", immediate);
+    env.add_immediate(";", end_definition);
+    env.add_immediate("[", left_bracket);
+    env.add_immediate("literal", literal);
+    env.add_immediate("if", compile_if);
+    env.add_immediate("else", compile_else);
+    env.add_immediate("then", compile_then);
+    env.add_immediate("do", compile_do);
+    env.add_immediate("loop", compile_loop);
+    env.add_immediate("recurse", recurse);
+    env.add_immediate("does>", does);
+
+    // Execution tokens
+    env.add_builtin("execute", execute);
+    env.add_builtin("defer!", defer_store);
+    env.add_builtin(">body", to_body);
+    env.add_builtin("compile,", compile_comma);
+    env.add_builtin("defer@", defer_fetch);
+
+    // Structures
+    env.add_builtin("end-structure", end_structure);
+
+    // Search order, kept in the root wordlist
+    env.add_root_builtin("wordlist", wordlist);
+    env.add_root_builtin("forth-wordlist", forth_wordlist);
+    env.add_root_builtin("search-wordlist", search_wordlist);
+    env.add_root_builtin("get-order", get_order);
+    env.add_root_builtin("set-order", set_order);
+    env.add_root_builtin("get-current", get_current);
+    env.add_root_builtin("set-current", set_current);
+    env.add_root_builtin("definitions", definitions);
+    env.add_root_builtin("forth", forth);
+    env.add_root_builtin("only", only);
+    env.add_root_builtin("also", also);
+    env.add_root_builtin("previous", previous);
+    env.add_root_builtin("order", order);
+
+    // Number base
+    env.add_builtin("hex", hex);
+    env.add_builtin("decimal", decimal);
+    env.add_builtin("binary", binary);
+}
+
+#[cfg(test)]
+mod tests {
+    use forth::env::ForthEnv;
+    use forth::inter::tests::{eval, run};
+
+    #[test]
+    fn compiling_words_keep_the_stack_when_interpreting() {
+        let mut env = ForthEnv::empty();
+        let not_compiling = Err("Not compiling a definition".to_string());
+        assert_eq!(eval(&mut env, "5 literal"), not_compiling);
+        assert_eq!(eval(&mut env, "' dup compile,"), not_compiling);
+        assert_eq!(env.depth(), 2);
+        assert_eq!(run(": f [ 2 3 + ] literal ; f"), Ok(vec![5]));
+        assert_eq!(
+            run(": g [ ' dup ] literal compile, ; immediate : h g ; 4 h"),
+            Ok(vec![4, 4])
+        );
+    }
+
+    #[test]
+    fn pick_and_roll_check_their_index() {
+        assert_eq!(run("1 2 3 0 pick"), Ok(vec![1, 2, 3, 3]));
+        assert_eq!(run("1 2 3 2 pick"), Ok(vec![1, 2, 3, 1]));
+        assert_eq!(run("1 2 3 2 roll"), Ok(vec![2, 3, 1]));
+        assert_eq!(run("1 2 3 0 roll"), Ok(vec![1, 2, 3]));
+
+        let pick = |n: i32| Err(format!("Cannot pick item {} when stack depth is: 3", n));
+        assert_eq!(run("1 2 3 3 pick"), pick(3));
+        assert_eq!(run("1 2 3 -1 pick"), pick(-1));
+        let roll = |n: i32| Err(format!("Cannot roll item {} when stack depth is: 3", n));
+        assert_eq!(run("1 2 3 3 roll"), roll(3));
+        assert_eq!(run("1 2 3 -1 roll"), roll(-1));
+        assert_eq!(run("pick"), Err("Empty stack for pick".to_string()));
+    }
+
+    #[test]
+    fn stack_words_rearrange_items() {
+        assert_eq!(run("depth 7 8 depth"), Ok(vec![0, 7, 8, 3]));
+        assert_eq!(run("5 ?dup 0 ?dup"), Ok(vec![5, 5, 0]));
+        assert_eq!(run("1 2 3 -rot"), Ok(vec![3, 1, 2]));
+        assert_eq!(run("1 2 nip 3 4 tuck"), Ok(vec![2, 4, 3, 4]));
+        assert_eq!(run("1 2 clear depth"), Ok(vec![0]));
+        assert!(run("1 2 -rot").is_err());
+        assert!(run("?dup").is_err());
+    }
+
+    #[test]
+    fn dynamic_strings_grow() {
+        let src = "str-new dup s\" ab\" str-append dup 233 str-append-char dup str-len";
+        assert_eq!(run(src), Ok(vec![1, 4]));
+        let src = "str-new dup s\" 12\" str-append str-new dup s\" 3\" str-append
+over swap str-append-str str>number";
+        assert_eq!(run(src), Ok(vec![123, -1]));
+        assert_eq!(run("255 hex number>str str>number"), Ok(vec![255, -1]));
+        assert_eq!(
+            run("str-new dup s\" x1\" str-append str>number"),
+            Ok(vec![0])
+        );
+        assert_eq!(
+            run("str-new dup s\" ab\" str-append dup str-clear str-len"),
+            Ok(vec![0])
+        );
+    }
+
+    #[test]
+    fn freed_strings_are_invalid() {
+        assert_eq!(run("str-new str-new swap str-free str-new"), Ok(vec![2, 1]));
+        let invalid = Err("Invalid string handle: 1".to_string());
+        assert_eq!(run("str-new dup str-free str-len"), invalid);
+        assert_eq!(run("str-new dup str-free str-free"), invalid);
+        assert_eq!(
+            run("0 str-len"),
+            Err("Invalid string handle: 0".to_string())
+        );
+        assert_eq!(
+            run("-1 str-len"),
+            Err("Invalid string handle: -1".to_string())
+        );
+    }
+
+    #[test]
+    fn emit_rejects_invalid_code_points() {
+        for &x in &[-1, 0xd800, 0xdfff, 0x110000] {
+            let res = run(&format!("{} emit", x));
+            assert_eq!(res, Err(format!("Invalid character code: {}", x)));
+        }
+        assert!(run("55296 xc-size").is_err());
+        assert!(run("here 55296 swap xc!+").is_err());
+    }
+
+    #[test]
+    fn extended_characters_use_utf8() {
+        assert_eq!(
+            run("65 xc-size 233 xc-size 8364 xc-size 128512 xc-size"),
+            Ok(vec![1, 2, 3, 4])
+        );
+        assert_eq!(
+            run("here 4 allot dup 8364 swap xc!+ over - swap xc@+ nip"),
+            Ok(vec![3, 8364])
+        );
+        assert_eq!(run("s\" aé€\" x-width"), Ok(vec![3]));
+        assert_eq!(run("s\" é€\" x-size"), Ok(vec![2]));
+        assert_eq!(run("s\" é€\" +x/string nip"), Ok(vec![3]));
+        assert_eq!(run("s\" é€\" x\\string- nip"), Ok(vec![2]));
+        assert_eq!(
+            run("s\" é€\" drop dup xchar+ xchar+ xchar- swap -"),
+            Ok(vec![2])
+        );
+    }
+
+    #[test]
+    fn string_addresses_do_not_overflow() {
+        assert!(run("2147483647 1 type").is_err());
+        assert!(run("s\" x\" 2147483647 compare").is_err());
+        assert!(run("2147483647 5 2 /string").is_err());
+        assert!(run("-2147483648 xchar-").is_err());
+        assert_eq!(run("10 5 2 /string"), Ok(vec![12, 3]));
+    }
+
+    #[test]
+    fn search_order_words_stay_visible() {
+        assert_eq!(run("vocabulary v v forth 1 dup"), Ok(vec![1, 1]));
+        assert_eq!(run("vocabulary v v only forth 2 dup"), Ok(vec![2, 2]));
+        assert_eq!(run("only 3 dup"), Err("Invalid token: dup".to_string()));
+        assert!(run("only previous previous").is_err());
+    }
+
+    #[test]
+    fn set_order_checks_its_count() {
+        let res = run("-5 set-order");
+        assert_eq!(
+            res,
+            Err("Cannot set search order of -5 wordlists when stack depth is: 0".to_string())
+        );
+        let mut env = ForthEnv::empty();
+        assert!(eval(&mut env, "-5 set-order").is_err());
+        assert!(eval(&mut env, "only forth").is_ok());
+        assert_eq!(env.get_order(), &[0, 1]);
+        assert!(run("forth-wordlist 2 set-order").is_err());
+        assert_eq!(
+            run("7 forth-wordlist 2 set-order"),
+            Err("Invalid wordlist: 7".to_string())
+        );
+        assert_eq!(run("-1 set-order get-order"), Ok(vec![1, 1, 2]));
+    }
+
+    #[test]
+    fn vocabularies_hold_their_definitions() {
+        let src = "vocabulary v also v definitions 7 constant k previous definitions";
+        assert_eq!(
+            run(&format!("{} k", src)),
+            Err("Invalid token: k".to_string())
+        );
+        assert_eq!(run(&format!("{} also v k", src)), Ok(vec![7]));
+    }
+
+    #[test]
+    fn pictured_output_takes_double_cells() {
+        assert_eq!(run("123 0 <# #s #>"), Ok(vec![0, 3]));
+        assert_eq!(
+            run("123 0 <# #s #> drop dup c@ swap 2 + c@"),
+            Ok(vec![49, 51])
+        );
+        assert_eq!(run("123 0 <# # #>"), Ok(vec![0, 1]));
+        assert_eq!(run("123 0 <# # # # # #s"), Ok(vec![0, 0]));
+        assert_eq!(run("-1 0 <# #s #> nip"), Ok(vec![10]));
+        assert_eq!(run("0 1 <# #s #> nip"), Ok(vec![10]));
+        assert_eq!(run("hex 0 1 <# #s #> nip"), Ok(vec![9]));
+        assert_eq!(run("-5 dup abs 0 <# #s rot sign #> drop c@"), Ok(vec![45]));
+        assert!(run("5 <# #s").is_err());
+    }
+
+    #[test]
+    fn allot_keeps_builtin_data() {
+        assert_eq!(run("-1 allot"), Err("Cannot allot -1 cells".to_string()));
+        assert_eq!(run("here 3 allot -3 allot here ="), Ok(vec![-1]));
+        assert!(run("2147483647 allot").is_err());
+        assert!(run("16777216 allot").is_err());
+    }
+
+    #[test]
+    fn allot_keeps_the_data_of_words() {
+        let cannot = Err("Cannot allot -1 cells".to_string());
+        assert_eq!(run("variable q -1 allot"), cannot);
+        assert_eq!(run("5 value v -1 allot"), cannot);
+        assert_eq!(run(": f s\" hi\" ; -1 allot"), cannot);
+        assert_eq!(run("create b 1 , 2 , -2 allot b here ="), Ok(vec![-1]));
+        assert_eq!(
+            run("create b 1 , -2 allot"),
+            Err("Cannot allot -2 cells".to_string())
+        );
+    }
+}
@@ -0,0 +1,454 @@
+use forth::valid_forth_name;
+
+use forth::compiler::{Compiler, Instr};
+use forth::env::{ForthEnv, ForthResult};
+
+// Parsing words, which read their argument from the input following them
+
+// Reads the name of a word being defined
+pub(crate) fn parse_name(env: &mut ForthEnv, what: &str) -> ForthResult<String> {
+    match env.next_word() {
+        Some(ref name) if !valid_forth_name(name) => {
+            Err(format!("Invalid name for {}: {}", what, name))
+        }
+        Some(name) => Ok(name),
+        None => Err(format!("Name not found for {}", what)),
+    }
+}
+
+// Execution token of the word named by the next word
+pub(crate) fn parse_xt(env: &mut ForthEnv) -> ForthResult<usize> {
+    match env.next_word() {
+        Some(name) => match env.find(&name) {
+            Some(xt) => Ok(xt),
+            None => Err(format!("Undefined word: {}", name)),
+        },
+        None => Err("Name not found for execution token".to_string()),
+    }
+}
+
+fn parse_deferred(env: &mut ForthEnv) -> ForthResult<usize> {
+    let xt = parse_xt(env)?;
+    env.defer_get(xt)?;
+    Ok(xt)
+}
+
+fn parse_string(env: &mut ForthEnv) -> ForthResult<Vec<u8>> {
+    match env.parse('"') {
+        Some(msg) => Ok(msg.into_bytes()),
+        None => Err(env.unterminated("string")),
+    }
+}
+
+// Runs the code when interpreting, or adds it to the current definition
+fn run_or_compile(env: &mut ForthEnv, code: &[Instr]) -> ForthResult<()> {
+    if env.is_compiling() {
+        let comp = env.compiler()?;
+        for &instr in code {
+            comp.emit(instr);
+        }
+        Ok(())
+    } else {
+        env.run(code)
+    }
+}
+
+// Leaves `addr len` for the string, which is copied into data space when
+// compiled and into a string buffer when interpreted
+fn string_literal(env: &mut ForthEnv, msg: &[u8]) -> ForthResult<()> {
+    if env.is_compiling() {
+        let addr = env.add_bytes(msg);
+        env.compiler()?.emit(Instr::String(addr, msg.len() as i32));
+    } else {
+        let cells: Vec<_> = msg.iter().map(|&b| i32::from(b)).collect();
+        let addr = env.add_transient(&cells)?;
+        env.push(addr);
+        env.push(msg.len() as i32);
+    }
+    Ok(())
+}
+
+// Comments
+pub fn paren(env: &mut ForthEnv) -> ForthResult<()> {
+    match env.parse(')') {
+        Some(text) => {
+            if let Ok(comp) = env.compiler() {
+                comp.comment(&text);
+            }
+            Ok(())
+        }
+        None => Err(env.unterminated("comment")),
+    }
+}
+
+pub fn backslash(env: &mut ForthEnv) -> ForthResult<()> {
+    env.parse('\n');
+    Ok(())
+}
+
+// Locals
+
+// Declares locals with `{: a b | c -- d :}`, where `a b` are taken from the
+// stack, `c` starts out as zero and everything after `--` is a comment
+pub fn brace_colon(env: &mut ForthEnv) -> ForthResult<()> {
+    let mut names = vec![];
+    let mut from_stack = None;
+    let mut comment = false;
+    let mut decl = vec!["{:".to_string()];
+
+    loop {
+        let word = env.next_word();
+        if let Some(ref w) = word {
+            decl.push(w.clone());
+        }
+        match word {
+            Some(ref w) if w == ":}" => break,
+            Some(_) if comment => {}
+            Some(ref w) if w == "--" => comment = true,
+            Some(ref w) if w == "|" && from_stack.is_none() => from_stack = Some(names.len()),
+            Some(name) => names.push(name),
+            None => return Err(env.unterminated("locals declaration")),
+        }
+    }
+
+    let from_stack = from_stack.unwrap_or(names.len());
+    env.compiler()?
+        .declare_locals(names, from_stack, decl.join(" "))
+}
+
+// Declares locals with `locals| a b |`, where `a` is taken from the top of
+// the stack
+pub fn locals_bar(env: &mut ForthEnv) -> ForthResult<()> {
+    let mut names = vec![];
+    loop {
+        match env.next_word() {
+            Some(ref w) if w == "|" => break,
+            Some(name) => names.push(name),
+            None => return Err(env.unterminated("locals declaration")),
+        }
+    }
+
+    let decl = format!("locals| {} |", names.join(" "));
+    names.reverse();
+    let from_stack = names.len();
+    env.compiler()?.declare_locals(names, from_stack, decl)
+}
+
+pub fn colon(env: &mut ForthEnv) -> ForthResult<()> {
+    let name = parse_name(env, "function")?;
+    let xt = env.reserve_function(&name);
+    env.begin_compile(Compiler::for_function(&name, xt));
+    Ok(())
+}
+
+pub fn variable(env: &mut ForthEnv) -> ForthResult<()> {
+    let name = parse_name(env, "variable")?;
+    env.add_variable(&name);
+    Ok(())
+}
+
+pub fn constant(env: &mut ForthEnv) -> ForthResult<()> {
+    let name = parse_name(env, "constant")?;
+    let x = env.pop(format!("Stack empty to set constant {}", name))?;
+    env.add_constant(&name, x);
+    Ok(())
+}
+
+pub fn create(env: &mut ForthEnv) -> ForthResult<()> {
+    let name = parse_name(env, "create")?;
+    env.add_created(&name);
+    Ok(())
+}
+
+// Structures
+//
+// `begin-structure name` leaves the structure and its size so far, which
+// each field adds to. `end-structure` makes `name` push the final size.
+pub fn begin_structure(env: &mut ForthEnv) -> ForthResult<()> {
+    let name = parse_name(env, "structure")?;
+    let xt = env.add_constant(&name, 0);
+    env.push(xt as i32);
+    env.push(0);
+    Ok(())
+}
+
+fn add_field(env: &mut ForthEnv, size: i32) -> ForthResult<()> {
+    let offset = env.pop("Empty stack for offset of field".to_string())?;
+    let name = parse_name(env, "field")?;
+    env.add_field(&name, offset);
+    env.push(offset.wrapping_add(size));
+    Ok(())
+}
+
+pub fn plus_field(env: &mut ForthEnv) -> ForthResult<()> {
+    let size = env.pop("Empty stack for size in +field".to_string())?;
+    add_field(env, size)
+}
+
+// Cells and chars both take a single address unit
+pub fn field_colon(env: &mut ForthEnv) -> ForthResult<()> {
+    add_field(env, 1)
+}
+
+pub fn value(env: &mut ForthEnv) -> ForthResult<()> {
+    let name = parse_name(env, "value")?;
+    let x = env.pop(format!("Stack empty to set value {}", name))?;
+    env.add_value(&name, x);
+    Ok(())
+}
+
+// Stores into the value or local named by the next word, or with `add` set
+// adds to it
+fn store_to(env: &mut ForthEnv, add: bool) -> ForthResult<()> {
+    let name = match env.next_word() {
+        Some(name) => name,
+        None => return Err("Name not found for to".to_string()),
+    };
+
+    if env.is_compiling() {
+        if let Some(index) = env.compiler()?.local(&name) {
+            let instr = if add {
+                Instr::PlusToLocal(index)
+            } else {
+                Instr::ToLocal(index)
+            };
+            env.compiler()?.emit(instr);
+            return Ok(());
+        }
+    }
+
+    let xt = match env.find(&name) {
+        Some(xt) => xt,
+        None => return Err(format!("Undefined word: {}", name)),
+    };
+    env.value_addr(xt)?;
+
+    let instr = if add {
+        Instr::PlusToValue(xt)
+    } else {
+        Instr::ToValue(xt)
+    };
+    run_or_compile(env, &[instr])
+}
+
+pub fn to(env: &mut ForthEnv) -> ForthResult<()> {
+    store_to(env, false)
+}
+
+pub fn plus_to(env: &mut ForthEnv) -> ForthResult<()> {
+    store_to(env, true)
+}
+
+pub fn defer(env: &mut ForthEnv) -> ForthResult<()> {
+    let name = parse_name(env, "deferred word")?;
+    env.add_deferred(&name);
+    Ok(())
+}
+
+pub fn vocabulary(env: &mut ForthEnv) -> ForthResult<()> {
+    let name = parse_name(env, "vocabulary")?;
+    env.add_vocabulary(&name);
+    Ok(())
+}
+
+pub fn marker(env: &mut ForthEnv) -> ForthResult<()> {
+    let name = parse_name(env, "marker")?;
+    env.add_marker(&name);
+    Ok(())
+}
+
+pub fn forget(env: &mut ForthEnv) -> ForthResult<()> {
+    let xt = parse_xt(env)?;
+    env.forget(xt)
+}
+
+pub fn see(env: &mut ForthEnv) -> ForthResult<()> {
+    let xt = parse_xt(env)?;
+    println!("{}", env.decompile(xt)?);
+    Ok(())
+}
+
+pub fn tick(env: &mut ForthEnv) -> ForthResult<()> {
+    let xt = parse_xt(env)?;
+    env.push(xt as i32);
+    Ok(())
+}
+
+pub fn bracket_tick(env: &mut ForthEnv) -> ForthResult<()> {
+    let xt = parse_xt(env)?;
+    env.compiler()?.emit(Instr::Xt(xt));
+    Ok(())
+}
+
+// Compiles the compilation behaviour of the next word: immediate words
+// get executed when the current definition runs, while other words get
+// compiled into whichever definition is being compiled at that point
+pub fn postpone(env: &mut ForthEnv) -> ForthResult<()> {
+    let xt = parse_xt(env)?;
+    let instr = if env.is_immediate(xt) {
+        Instr::Call(xt)
+    } else {
+        Instr::Compile(xt)
+    };
+
+    env.compiler()?.emit(instr);
+    Ok(())
+}
+
+pub fn is(env: &mut ForthEnv) -> ForthResult<()> {
+    let xt = parse_deferred(env)?;
+    run_or_compile(env, &[Instr::Is(xt)])
+}
+
+pub fn action_of(env: &mut ForthEnv) -> ForthResult<()> {
+    let xt = parse_deferred(env)?;
+    run_or_compile(env, &[Instr::ActionOf(xt)])
+}
+
+pub fn dot_quote(env: &mut ForthEnv) -> ForthResult<()> {
+    let msg = parse_string(env)?;
+    if env.is_compiling() {
+        let addr = env.add_bytes(&msg);
+        env.compiler()?.emit(Instr::Print(addr, msg.len() as i32));
+    } else {
+        print!("{}", String::from_utf8_lossy(&msg));
+    }
+    Ok(())
+}
+
+pub fn s_quote(env: &mut ForthEnv) -> ForthResult<()> {
+    let msg = parse_string(env)?;
+    string_literal(env, &msg)
+}
+
+pub fn s_backslash_quote(env: &mut ForthEnv) -> ForthResult<()> {
+    let msg = env.parse_escaped()?;
+    string_literal(env, &msg)
+}
+
+pub fn c_quote(env: &mut ForthEnv) -> ForthResult<()> {
+    let msg = parse_string(env)?;
+    if env.is_compiling() {
+        let addr = env.here();
+        env.comma(msg.len() as i32);
+        env.add_bytes(&msg);
+        env.compiler()?.emit(Instr::CString(addr));
+    } else {
+        let mut cells = vec![msg.len() as i32];
+        cells.extend(msg.iter().map(|&b| i32::from(b)));
+        let addr = env.add_transient(&cells)?;
+        env.push(addr);
+    }
+    Ok(())
+}
+
+// Registers the parsing words
+pub fn init(env: &mut ForthEnv) {
+    // Defining words
+    env.add_builtin(":", colon);
+    env.add_builtin("variable", variable);
+    env.add_builtin("constant", constant);
+    env.add_builtin("value", value);
+    env.add_builtin("create", create);
+    env.add_builtin("defer", defer);
+    env.add_builtin("vocabulary", vocabulary);
+    env.add_builtin("marker", marker);
+    env.add_builtin("forget", forget);
+
+    // Comments
+    env.add_immediate("(", paren);
+    env.add_immediate("\\", backslash);
+
+    // Structures
+    env.add_builtin("begin-structure", begin_structure);
+    env.add_builtin("+field", plus_field);
+    env.add_builtin("field:", field_colon);
+    env.add_builtin("cfield:", field_colon);
+
+    // Values
+    env.add_immediate("to", to);
+    env.add_immediate("+to", plus_to);
+
+    // Locals
+    env.add_immediate("{:", brace_colon);
+    env.add_immediate("locals|", locals_bar);
+
+    // Execution tokens
+    env.add_builtin("'", tick);
+    env.add_immediate("[']", bracket_tick);
+    env.add_immediate("postpone", postpone);
+    env.add_immediate("is", is);
+    env.add_immediate("action-of", action_of);
+    env.add_builtin("see", see);
+
+    // Strings
+    env.add_immediate(".\"", dot_quote);
+    env.add_immediate("s\"", s_quote);
+    env.add_immediate("s\\\"", s_backslash_quote);
+    env.add_immediate("c\"", c_quote);
+}
+
+#[cfg(test)]
+mod tests {
+    use forth::inter::tests::run;
+
+    #[test]
+    fn interpreted_strings_leave_data_space_alone() {
+        assert_eq!(
+            run("create t 1 , s\" xy\" drop drop 2 , t 1 + @"),
+            Ok(vec![2])
+        );
+        assert_eq!(run("here c\" abc\" drop here ="), Ok(vec![-1]));
+        assert_eq!(run("s\" ab\" s\" ab\" compare"), Ok(vec![0]));
+        assert_eq!(run("c\" abc\" count nip"), Ok(vec![3]));
+    }
+
+    #[test]
+    fn locals_take_values_from_the_stack() {
+        assert_eq!(run(": f {: a b | c :} a b - to c c ;\n5 3 f"), Ok(vec![2]));
+        assert_eq!(run(": g locals| x y | x y - ;\n1 2 g"), Ok(vec![1]));
+        assert_eq!(run(": h {: a :} 5 +to a a ;\n1 h"), Ok(vec![6]));
+    }
+
+    #[test]
+    fn locals_are_not_declared_in_control_structures() {
+        let nested = Err("Locals declared inside a control structure".to_string());
+        assert_eq!(run(": inner 0 if {: a :} then ;"), nested);
+        assert_eq!(run(": inner 2 0 do {: a :} loop ;"), nested);
+    }
+
+    #[test]
+    fn empty_locals_declarations_end_their_frame() {
+        let src = ": g {: -- x :} ; : f {: a :} g a ; 5 f";
+        assert_eq!(run(src), Ok(vec![5]));
+        assert_eq!(
+            run(": h locals| | 1 ; : f {: a :} h a ; 5 f"),
+            Ok(vec![1, 5])
+        );
+        let again = Err("Locals already declared".to_string());
+        assert_eq!(run(": f {: -- x :} {: a :} ;"), again);
+    }
+
+    #[test]
+    fn values_and_deferred_words_are_set_by_name() {
+        assert_eq!(run("3 value v 4 to v 2 +to v v"), Ok(vec![6]));
+        assert_eq!(run("3 value v : f 4 to v 2 +to v v ; f"), Ok(vec![6]));
+        assert_eq!(run(": f {: a :} 2 +to a a ; 5 f"), Ok(vec![7]));
+        let src = ": a 1 ; defer d : f ['] a is d action-of d ; f ' a = d";
+        assert_eq!(run(src), Ok(vec![-1, 1]));
+        let src = "defer d : f action-of d ; f";
+        assert_eq!(run(src), Err("Deferred word d has no action".to_string()));
+        assert_eq!(
+            run("variable x 1 to x"),
+            Err("x is not a value".to_string())
+        );
+    }
+
+    #[test]
+    fn compiled_strings_stay_valid() {
+        assert_eq!(
+            run(": s s\" xyz\" ;\ns\" a\" drop drop s\" b\" drop drop s nip"),
+            Ok(vec![3])
+        );
+    }
+}
@@ -1,32 +1,70 @@
+mod columns;
+mod commands;
+mod editor;
 mod forth;
 
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, IsTerminal};
 
-fn to_quit(cmd: &str) -> bool {
-    match cmd {
-        "quit" | "q" | "exit" => true,
-        _ => false,
+use commands::Session;
+use editor::Editor;
+
+// Interactive session with line editing and history
+fn run_repl(session: &mut Session) {
+    let mut editor = Editor::new();
+
+    loop {
+        // Unfinished input carries on with a continuation prompt
+        let prompt = if session.env.is_incomplete() {
+            "... "
+        } else {
+            "> "
+        };
+        let input = match editor.read_line(prompt, session) {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {
+                session.env.cancel_input();
+                continue;
+            }
+            Err(e) => {
+                println!("Error: {}", e);
+                return run_lines(session);
+            }
+        };
+        editor.add_history(&input);
+
+        let input = input.trim();
+        if !session.run(input) {
+            break;
+        }
     }
+    println!("Bye!");
 }
 
-fn run_forth() {
-    let mut env = forth::env::ForthEnv::empty();
-    let intr = forth::inter::Interpreter::new();
-
+// Reads lines from input which is not a terminal, such as a pipe
+fn run_lines(session: &mut Session) {
     let stdin = io::stdin();
     for line in stdin.lock().lines() {
         let mut input = line.unwrap();
         input = input.trim().to_string();
 
-        if to_quit(&input) {
+        if !session.run(&input) {
             println!("Bye!");
             return;
-        } else {
-            intr.eval(&mut env, &input);
         }
     }
 }
 
+fn run_forth() {
+    let mut session = Session::new();
+
+    if io::stdin().is_terminal() {
+        run_repl(&mut session);
+    } else {
+        run_lines(&mut session);
+    }
+}
+
 fn main() {
     run_forth();
 }